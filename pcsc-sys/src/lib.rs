@@ -196,6 +196,22 @@ pub struct SCARD_READERSTATE {
     pub rgbAtr: [u8; ATR_BUFFER_SIZE],
 }
 
+#[cfg(target_os = "windows")]
+#[repr(C)]
+pub struct GUID {
+    pub Data1: u32,
+    pub Data2: u16,
+    pub Data3: u16,
+    pub Data4: [u8; 8],
+}
+
+#[cfg(target_os = "windows")]
+pub const SCARD_PROVIDER_PRIMARY: DWORD = 1;
+#[cfg(target_os = "windows")]
+pub const SCARD_PROVIDER_CSP: DWORD = 2;
+#[cfg(target_os = "windows")]
+pub const SCARD_PROVIDER_KSP: DWORD = 3;
+
 pub const SCARD_CLASS_VENDOR_INFO: ULONG = 1;
 pub const SCARD_CLASS_COMMUNICATIONS: ULONG = 2;
 pub const SCARD_CLASS_PROTOCOL: ULONG = 3;
@@ -394,4 +410,25 @@ extern "system" {
         cbRecvLength: DWORD,
         lpBytesReturned: *mut DWORD,
     ) -> LONG;
+
+    #[cfg(target_os = "windows")]
+    #[link_name = "SCardListCardsA"]
+    pub fn SCardListCards(
+        hContext: SCARDCONTEXT,
+        pbAtr: *const u8,
+        rgquidInterfaces: *const GUID,
+        cguidInterfaceCount: DWORD,
+        mszCards: *mut c_char,
+        pcchCards: *mut DWORD,
+    ) -> LONG;
+
+    #[cfg(target_os = "windows")]
+    #[link_name = "SCardGetCardTypeProviderNameA"]
+    pub fn SCardGetCardTypeProviderName(
+        hContext: SCARDCONTEXT,
+        szCardName: *const c_char,
+        dwProviderId: DWORD,
+        szProvider: *mut c_char,
+        pcchProvider: *mut DWORD,
+    ) -> LONG;
 }
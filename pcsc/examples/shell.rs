@@ -0,0 +1,94 @@
+// Example of an interactive shell for sending commands to a smart card.
+//
+// Each line read from stdin is either an APDU command in hex (e.g.
+// `00 A4 04 00 00`), or `.control <code> <hex>` to send a control command
+// to the reader. The code is the function number passed to `ctl_code`, in
+// decimal or in hex with a `0x` prefix (e.g. `3400` or `0xD48`). The card
+// in the first reader is used.
+
+extern crate pcsc;
+
+use pcsc::*;
+use std::io::BufRead;
+
+// Parse a control function number, in decimal or `0x`-prefixed hex.
+fn parse_code(code: &str) -> Option<u32> {
+    if code.starts_with("0x") || code.starts_with("0X") {
+        u32::from_str_radix(&code[2..], 16).ok()
+    } else {
+        code.parse().ok()
+    }
+}
+
+fn run_command(card: &Card, line: &str) -> Result<(), Error> {
+    if line.starts_with(".control") {
+        let mut args = line[".control".len()..].trim().splitn(2, ' ');
+        let code = args.next().and_then(parse_code);
+        let data = hex::decode(args.next().unwrap_or("")).ok();
+        let (code, data) = match (code, data) {
+            (Some(code), Some(data)) => (code, data),
+            _ => {
+                println!("usage: .control <code> <hex>, with <code> in decimal or 0x-prefixed hex");
+                return Ok(());
+            }
+        };
+        let mut response_buf = [0; MAX_BUFFER_SIZE];
+        let response = card.control(ctl_code(code.into()), &data, &mut response_buf)?;
+        println!("{}", hex::encode(response));
+    } else {
+        let (data, sw) = card.transmit_hex(line)?;
+        println!("{}", hex::encode(&data));
+        println!("SW: {}", iso7816::StatusWord::new(sw));
+    }
+    Ok(())
+}
+
+fn main() {
+    let ctx = Context::establish(Scope::User).expect("failed to establish context");
+
+    let readers = ctx.list_readers_owned().expect("failed to list readers");
+    let reader = match readers.first() {
+        Some(reader) => reader,
+        None => {
+            println!("No readers are connected.");
+            return;
+        }
+    };
+    println!("Using reader: {:?}", reader);
+
+    let mut card = ctx.connect(reader, ShareMode::Shared, Protocols::ANY).expect("failed to connect to card");
+
+    let stdin = std::io::stdin();
+    for line in stdin.lock().lines() {
+        let line = line.expect("failed to read stdin");
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        match run_command(&card, line) {
+            Ok(()) => {}
+            Err(Error::InvalidParameter) => println!("Invalid command."),
+            // The card was reset by someone else; reconnect to it. The
+            // command is not retried, since the card state was lost.
+            Err(Error::ResetCard) => {
+                println!("The card was reset, reconnecting.");
+                card.reconnect(ShareMode::Shared, Protocols::ANY, Disposition::LeaveCard).expect("failed to reconnect to card");
+            }
+            // The card was removed; wait for a card and connect to it.
+            Err(Error::RemovedCard) => {
+                println!("The card was removed, insert a card.");
+                let mut reader_states = vec![ReaderState::new(reader.clone(), State::UNAWARE)];
+                loop {
+                    ctx.get_status_change(None, &mut reader_states).expect("failed to get status change");
+                    if reader_states[0].event_state().contains(State::PRESENT) {
+                        break;
+                    }
+                    reader_states[0].sync_current_state();
+                }
+                card = ctx.connect(reader, ShareMode::Shared, Protocols::ANY).expect("failed to connect to card");
+            }
+            Err(err) => println!("Error: {}", err),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! Conversion of bytes to and from hex strings.
+//!
+//! APDUs, ATRs and the like are usually written as hex strings, for
+//! example `00 A4 04 00 00`. These functions convert such strings to
+//! bytes and back.
+
+/// Possible hex decoding errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HexError {
+    /// The number of hex digits is odd.
+    OddLength,
+    /// The string contains a character which is not a hex digit.
+    InvalidCharacter(char),
+}
+
+impl std::error::Error for HexError {
+    fn description(&self) -> &str {
+        match *self {
+            HexError::OddLength => "The number of hex digits is odd",
+            HexError::InvalidCharacter(_) => "The string contains a character which is not a hex digit",
+        }
+    }
+}
+
+impl std::fmt::Display for HexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        match *self {
+            HexError::OddLength => f.write_str(std::error::Error::description(self)),
+            HexError::InvalidCharacter(c) => write!(f, "Invalid hex digit {:?}", c),
+        }
+    }
+}
+
+/// Decode a hex string into bytes.
+///
+/// The hex digits may be in any case. Whitespace is ignored, and each
+/// whitespace-separated group of digits may have a `0x` prefix, so
+/// `"00A4"`, `"00 a4"` and `"0x00 0xA4"` all decode to `[0x00, 0xA4]`.
+pub fn decode(s: &str) -> Result<Vec<u8>, HexError> {
+    let mut bytes = Vec::new();
+    let mut high = None;
+    for group in s.split_whitespace() {
+        let group = if group.starts_with("0x") || group.starts_with("0X") {
+            &group[2..]
+        } else {
+            group
+        };
+        for c in group.chars() {
+            let digit = match c.to_digit(16) {
+                Some(digit) => digit as u8,
+                None => return Err(HexError::InvalidCharacter(c)),
+            };
+            high = match high {
+                Some(high) => {
+                    bytes.push((high << 4) | digit);
+                    None
+                },
+                None => Some(digit),
+            };
+        }
+    }
+    match high {
+        Some(_) => Err(HexError::OddLength),
+        None => Ok(bytes),
+    }
+}
+
+/// Encode bytes into a hex string, with uppercase digits and no
+/// separators, e.g. `"00A4"`.
+pub fn encode(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        s.push_str(&format!("{:02X}", b));
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_plain() {
+        assert_eq!(decode("00a4FF"), Ok(vec![0x00, 0xA4, 0xFF]));
+        assert_eq!(decode(""), Ok(vec![]));
+    }
+
+    #[test]
+    fn decode_whitespace_and_prefix() {
+        assert_eq!(decode(" 00 A4\t04\n00 "), Ok(vec![0x00, 0xA4, 0x04, 0x00]));
+        assert_eq!(decode("0x00 0XA4 04"), Ok(vec![0x00, 0xA4, 0x04]));
+        assert_eq!(decode("0x00A4"), Ok(vec![0x00, 0xA4]));
+    }
+
+    #[test]
+    fn decode_lone_prefix() {
+        assert_eq!(decode("0x"), Ok(vec![]));
+        assert_eq!(decode("00 0x A4"), Ok(vec![0x00, 0xA4]));
+    }
+
+    #[test]
+    fn decode_odd_length() {
+        assert_eq!(decode("0"), Err(HexError::OddLength));
+        assert_eq!(decode("00 A"), Err(HexError::OddLength));
+    }
+
+    #[test]
+    fn decode_invalid_character() {
+        assert_eq!(decode("0G"), Err(HexError::InvalidCharacter('G')));
+        assert_eq!(decode("00,A4"), Err(HexError::InvalidCharacter(',')));
+        // The prefix is only stripped at the start of a group.
+        assert_eq!(decode("000x"), Err(HexError::InvalidCharacter('x')));
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        assert_eq!(encode(&[]), "");
+        assert_eq!(encode(&[0x00, 0xA4, 0x0F, 0xFF]), "00A40FFF");
+        let bytes: Vec<u8> = (0..256).map(|b| b as u8).collect();
+        assert_eq!(decode(&encode(&bytes)), Ok(bytes));
+    }
+}
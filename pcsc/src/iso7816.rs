@@ -0,0 +1,133 @@
+//! Interpretation of ISO 7816-4 status words.
+//!
+//! Every APDU response ends with a 2-byte status word (SW1 SW2), which
+//! indicates the result of the command. See [ISO 7816 Part 4][1] section
+//! 5.1.3 for the meaning of the status words.
+//!
+//! [1]: http://www.cardwerk.com/smartcards/smartcard_standard_ISO7816-4_5_basic_organizations.aspx
+
+/// A status word of an APDU response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StatusWord {
+    sw: u16,
+}
+
+impl StatusWord {
+    /// Create a status word from its value, e.g. `0x9000`.
+    pub fn new(sw: u16) -> StatusWord {
+        StatusWord {
+            sw,
+        }
+    }
+
+    /// Create a status word from its bytes.
+    pub fn from_bytes(sw1: u8, sw2: u8) -> StatusWord {
+        StatusWord::new((u16::from(sw1) << 8) | u16::from(sw2))
+    }
+
+    /// The value of the status word, e.g. `0x9000`.
+    pub fn value(&self) -> u16 {
+        self.sw
+    }
+
+    /// The first byte of the status word.
+    pub fn sw1(&self) -> u8 {
+        (self.sw >> 8) as u8
+    }
+
+    /// The second byte of the status word.
+    pub fn sw2(&self) -> u8 {
+        self.sw as u8
+    }
+
+    /// Whether the status word indicates success (`90 00`).
+    pub fn is_success(&self) -> bool {
+        self.sw == 0x9000
+    }
+
+    /// A short description of the meaning of the status word.
+    ///
+    /// Status words which are not defined by ISO 7816-4 are described
+    /// according to their first byte, if possible.
+    pub fn description(&self) -> &'static str {
+        match self.sw {
+            0x9000 => "Success",
+            0x6281 => "Part of the returned data may be corrupted",
+            0x6282 => "End of file reached before reading Le bytes",
+            0x6283 => "Selected file invalidated",
+            0x6581 => "Memory failure",
+            0x6700 => "Wrong length",
+            0x6882 => "Secure messaging not supported",
+            0x6982 => "Security status not satisfied",
+            0x6983 => "Authentication method blocked",
+            0x6984 => "Reference data not usable",
+            0x6985 => "Conditions of use not satisfied",
+            0x6986 => "Command not allowed (no current EF)",
+            0x6A80 => "Incorrect parameters in the command data field",
+            0x6A81 => "Function not supported",
+            0x6A82 => "File or application not found",
+            0x6A83 => "Record not found",
+            0x6A84 => "Not enough memory space in the file",
+            0x6A86 => "Incorrect parameters P1-P2",
+            0x6A88 => "Referenced data not found",
+            0x6B00 => "Wrong parameters P1-P2 (offset outside the EF)",
+            0x6D00 => "Instruction code not supported or invalid",
+            0x6E00 => "Class not supported",
+            0x6F00 => "No precise diagnosis",
+            _ => match self.sw1() {
+                0x61 => "More data available",
+                0x62 => "Warning: state of non-volatile memory unchanged",
+                0x63 if self.sw2() & 0xF0 == 0xC0 => "Verification failed",
+                0x63 => "Warning: state of non-volatile memory changed",
+                0x64 => "Execution error: state of non-volatile memory unchanged",
+                0x65 => "Execution error: state of non-volatile memory changed",
+                0x66 => "Security-related issue",
+                0x67 => "Wrong length",
+                0x68 => "Functions in CLA not supported",
+                0x69 => "Command not allowed",
+                0x6A => "Wrong parameters P1-P2",
+                0x6C => "Wrong Le field",
+                _ => "Unknown status word",
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for StatusWord {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "{:04X} ({})", self.sw, self.description())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split() {
+        let sw = StatusWord::new(0x6A82);
+        assert_eq!(sw.sw1(), 0x6A);
+        assert_eq!(sw.sw2(), 0x82);
+        assert_eq!(StatusWord::from_bytes(0x6A, 0x82), sw);
+        assert_eq!(sw.value(), 0x6A82);
+        assert!(!sw.is_success());
+        assert!(StatusWord::from_bytes(0x90, 0x00).is_success());
+    }
+
+    #[test]
+    fn more_data_and_wrong_le() {
+        assert_eq!(StatusWord::new(0x6110).description(), "More data available");
+        assert_eq!(StatusWord::new(0x6100).description(), "More data available");
+        assert_eq!(StatusWord::new(0x6C10).description(), "Wrong Le field");
+        assert_eq!(StatusWord::new(0x6CFF).description(), "Wrong Le field");
+        assert_eq!(StatusWord::new(0x6C10).to_string(), "6C10 (Wrong Le field)");
+    }
+
+    #[test]
+    fn specific_and_unknown() {
+        assert_eq!(StatusWord::new(0x6A82).description(), "File or application not found");
+        assert_eq!(StatusWord::new(0x63C2).description(), "Verification failed");
+        assert_eq!(StatusWord::new(0x6310).description(), "Warning: state of non-volatile memory changed");
+        assert_eq!(StatusWord::new(0x1234).description(), "Unknown status word");
+    }
+}
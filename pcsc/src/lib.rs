@@ -62,6 +62,12 @@
 //! variants only. (If you require Unicode names in Windows, please open
 //! an issue.)
 //!
+//! Note that the choice between the variants cannot be a Cargo feature.
+//! Features are unified across all crates in a build, so a feature which
+//! changes the string types of the API (e.g. from `&CStr` to `&OsStr`)
+//! would break any other crate in the build which uses this library
+//! without expecting it.
+//!
 //! Since ASCII is a subset of UTF-8, you can thus safely use UTF-8
 //! conversion functions such as `to_str()` to obtain an `&str`/`String`
 //! from this library -- but don't do this if you don't need to ☺
@@ -90,17 +96,35 @@
 //! See [MSDN][7] for more details.
 //!
 //! [7]: https://msdn.microsoft.com/en-us/library/ms953432.aspx#smartcardcspcook_topic2
+//!
+//! ## Note on event loops
+//!
+//! None of the PC/SC implementations expose a file descriptor or other
+//! handle which can be registered with an event loop (such as `epoll`
+//! or `mio`) to find out when `get_status_change()` would not block. In
+//! pcsclite, the socket to pcscd is private to the library.
+//!
+//! To integrate with an event loop, perform the monitoring in a
+//! dedicated thread with its own context, and forward the changes to the
+//! event loop using a channel or similar mechanism. Use a `Canceler`
+//! (see `Context::canceler()`) to stop the monitoring thread.
 
 #[macro_use]
 extern crate bitflags;
 extern crate pcsc_sys as ffi;
 
+pub mod hex;
+pub mod iso7816;
+pub mod tlv;
+
+use std::borrow::Cow;
 use std::ffi::{CStr, CString};
 use std::mem::{forget, transmute};
-use std::ops::Deref;
+use std::ops::{Deref, DerefMut};
 use std::os::raw::c_char;
 use std::ptr::{null, null_mut};
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use ffi::{DWORD, LONG};
 
@@ -169,14 +193,12 @@ pub enum Protocol {
 }
 
 impl Protocol {
-    fn from_raw(raw: DWORD) -> Protocol {
+    fn from_raw_opt(raw: DWORD) -> Option<Protocol> {
         match raw {
-            ffi::SCARD_PROTOCOL_T0 => Protocol::T0,
-            ffi::SCARD_PROTOCOL_T1 => Protocol::T1,
-            ffi::SCARD_PROTOCOL_RAW => Protocol::RAW,
-            // This should not be possible, since we only allow to select
-            // from Protocol's variants. Hence, we can panic.
-            _ => panic!("impossible protocol: {:#x}", raw),
+            ffi::SCARD_PROTOCOL_T0 => Some(Protocol::T0),
+            ffi::SCARD_PROTOCOL_T1 => Some(Protocol::T1),
+            ffi::SCARD_PROTOCOL_RAW => Some(Protocol::RAW),
+            _ => None,
         }
     }
 }
@@ -192,6 +214,16 @@ bitflags! {
     }
 }
 
+impl Default for Protocols {
+    /// The default is `Protocols::ANY`.
+    ///
+    /// Passing it to `Context::connect` negotiates any protocol supported
+    /// by the card.
+    fn default() -> Protocols {
+        Protocols::ANY
+    }
+}
+
 /// Disposition method when disconnecting from a card reader.
 #[repr(u32)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -312,6 +344,61 @@ impl Error {
     fn into_raw(self) -> LONG {
         LONG::from(self as u32 as i32)
     }
+
+    /// Check whether the error indicates that the PC/SC service (pcscd
+    /// with pcsclite) is not available.
+    ///
+    /// This is the case for `Error::NoService` (the service is not
+    /// running), `Error::ServiceStopped` (it was stopped while in use)
+    /// and `Error::Shutdown` (the system is shutting down). It is
+    /// distinct from there being no card readers, which is reported as an
+    /// empty list by `Context::list_readers`.
+    pub fn is_daemon_unavailable(&self) -> bool {
+        match *self {
+            Error::NoService
+            | Error::ServiceStopped
+            | Error::Shutdown => true,
+            _ => false,
+        }
+    }
+
+    /// Get a process exit code for the error, for command-line tools.
+    ///
+    /// The errors are grouped in categories, so that scripts can tell
+    /// the failure modes apart:
+    ///
+    /// - 2: no card reader (`UnknownReader`, `NoReadersAvailable`, ...).
+    /// - 3: no card (`NoSmartcard`, `RemovedCard`).
+    /// - 4: sharing (`SharingViolation`, `NotTransacted`).
+    /// - 5: transport (`CommError`, `UnresponsiveCard`, `ResetCard`, ...).
+    /// - 6: PC/SC service unavailable (`NoService`, `ServiceStopped`,
+    ///   `Shutdown`).
+    /// - 7: timeout or cancellation (`Timeout`, `Cancelled`, ...).
+    /// - 1: any other error.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Error::UnknownReader
+            | Error::NoReadersAvailable
+            | Error::ReaderUnavailable
+            | Error::ReaderUnsupported => 2,
+            Error::NoSmartcard
+            | Error::RemovedCard => 3,
+            Error::SharingViolation
+            | Error::NotTransacted => 4,
+            Error::CommError
+            | Error::CommDataLost
+            | Error::UnresponsiveCard
+            | Error::UnpoweredCard
+            | Error::ResetCard
+            | Error::ProtoMismatch => 5,
+            _ if self.is_daemon_unavailable() => 6,
+            Error::Timeout
+            | Error::Cancelled
+            | Error::SystemCancelled
+            | Error::CancelledByUser => 7,
+            _ => 1,
+        }
+    }
 }
 
 impl std::error::Error for Error {
@@ -392,6 +479,122 @@ impl std::fmt::Display for Error {
     }
 }
 
+/// The outcome of a failed `Context::try_connect`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConnectError {
+    /// There is no card in the reader (`Error::NoSmartcard`,
+    /// `Error::RemovedCard`).
+    NoCard,
+    /// The reader does not exist or is not available
+    /// (`Error::UnknownReader`, `Error::ReaderUnavailable`).
+    ReaderGone,
+    /// The card is used exclusively by another connection
+    /// (`Error::SharingViolation`).
+    Sharing,
+    /// Any other error.
+    Other(Error),
+}
+
+impl From<Error> for ConnectError {
+    fn from(err: Error) -> ConnectError {
+        match err {
+            Error::NoSmartcard | Error::RemovedCard => ConnectError::NoCard,
+            Error::UnknownReader | Error::ReaderUnavailable => ConnectError::ReaderGone,
+            Error::SharingViolation => ConnectError::Sharing,
+            err => ConnectError::Other(err),
+        }
+    }
+}
+
+impl std::error::Error for ConnectError {
+    fn description(&self) -> &str {
+        match *self {
+            ConnectError::NoCard => "There is no smart card in the reader",
+            ConnectError::ReaderGone => "The reader is not available",
+            ConnectError::Sharing => "The smart card is used exclusively by another connection",
+            ConnectError::Other(ref err) => std::error::Error::description(err),
+        }
+    }
+}
+
+impl std::fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        f.write_str(std::error::Error::description(self))
+    }
+}
+
+/// An unsuccessful status word returned by the card.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ApduError {
+    status_word: iso7816::StatusWord,
+}
+
+impl ApduError {
+    /// Create an error for an unsuccessful status word.
+    pub fn new(status_word: iso7816::StatusWord) -> ApduError {
+        ApduError {
+            status_word,
+        }
+    }
+
+    /// The status word returned by the card.
+    pub fn status_word(&self) -> iso7816::StatusWord {
+        self.status_word
+    }
+}
+
+impl std::error::Error for ApduError {
+    fn description(&self) -> &str {
+        "The card returned an unsuccessful status word"
+    }
+}
+
+impl std::fmt::Display for ApduError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "The card returned status word {}", self.status_word)
+    }
+}
+
+/// An error returned by the functions which exchange APDUs and check
+/// their status words, such as `Card::read_binary`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HelperError {
+    /// The PC/SC call failed.
+    Pcsc(Error),
+    /// The card returned an unsuccessful status word.
+    Apdu(ApduError),
+}
+
+impl From<Error> for HelperError {
+    fn from(err: Error) -> HelperError {
+        HelperError::Pcsc(err)
+    }
+}
+
+impl From<ApduError> for HelperError {
+    fn from(err: ApduError) -> HelperError {
+        HelperError::Apdu(err)
+    }
+}
+
+impl std::error::Error for HelperError {
+    fn description(&self) -> &str {
+        match *self {
+            HelperError::Pcsc(ref err) => std::error::Error::description(err),
+            HelperError::Apdu(ref err) => std::error::Error::description(err),
+        }
+    }
+}
+
+impl std::fmt::Display for HelperError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        match *self {
+            HelperError::Pcsc(ref err) => err.fmt(f),
+            HelperError::Apdu(ref err) => err.fmt(f),
+        }
+    }
+}
+
 macro_rules! try_pcsc {
     ($e:expr) => (match $e {
         ffi::SCARD_S_SUCCESS => (),
@@ -410,11 +613,197 @@ pub enum Scope {
 }
 
 impl Scope {
+    /// Convert a raw `SCARD_SCOPE_*` value to a `Scope`.
+    ///
+    /// Returns `None` if the value is not a known scope. This is the
+    /// inverse of `scope as u32`.
+    pub fn from_raw(value: DWORD) -> Option<Scope> {
+        match value {
+            ffi::SCARD_SCOPE_USER => Some(Scope::User),
+            ffi::SCARD_SCOPE_TERMINAL => Some(Scope::Terminal),
+            ffi::SCARD_SCOPE_SYSTEM => Some(Scope::System),
+            ffi::SCARD_SCOPE_GLOBAL => Some(Scope::Global),
+            _ => None,
+        }
+    }
+
+    fn into_raw(self) -> DWORD {
+        DWORD::from(self as u32)
+    }
+}
+
+/// A type of provider registered for a smart card type.
+#[cfg(target_os = "windows")]
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProviderId {
+    Primary = ffi::SCARD_PROVIDER_PRIMARY as u32,
+    Csp = ffi::SCARD_PROVIDER_CSP as u32,
+    Ksp = ffi::SCARD_PROVIDER_KSP as u32,
+}
+
+#[cfg(target_os = "windows")]
+impl ProviderId {
     fn into_raw(self) -> DWORD {
         DWORD::from(self as u32)
     }
 }
 
+/// A GUID (globally unique identifier), as used to identify card
+/// interfaces and cache entries in some Windows functions.
+///
+/// The bytes are stored in the order they appear in the string form,
+/// i.e. `{00112233-4455-6677-8899-AABBCCDDEEFF}` is the bytes `00` to
+/// `FF`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Guid {
+    bytes: [u8; 16],
+}
+
+/// An error returned when parsing a `Guid` from a string fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ParseGuidError;
+
+impl std::error::Error for ParseGuidError {
+    fn description(&self) -> &str {
+        "The string is not a valid GUID"
+    }
+}
+
+impl std::fmt::Display for ParseGuidError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        f.write_str(std::error::Error::description(self))
+    }
+}
+
+impl Guid {
+    /// Create a GUID from its bytes, in string order.
+    pub fn from_bytes(bytes: [u8; 16]) -> Guid {
+        Guid {
+            bytes,
+        }
+    }
+
+    /// Get the bytes of the GUID, in string order.
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        &self.bytes
+    }
+
+    /// Convert the GUID to the layout used by the Windows API.
+    #[cfg(target_os = "windows")]
+    pub fn into_raw(self) -> ffi::GUID {
+        let b = &self.bytes;
+        ffi::GUID {
+            Data1: (u32::from(b[0]) << 24) | (u32::from(b[1]) << 16) | (u32::from(b[2]) << 8) | u32::from(b[3]),
+            Data2: (u16::from(b[4]) << 8) | u16::from(b[5]),
+            Data3: (u16::from(b[6]) << 8) | u16::from(b[7]),
+            Data4: [b[8], b[9], b[10], b[11], b[12], b[13], b[14], b[15]],
+        }
+    }
+}
+
+impl std::str::FromStr for Guid {
+    type Err = ParseGuidError;
+
+    /// Parse a GUID in the standard format, e.g.
+    /// `{00112233-4455-6677-8899-AABBCCDDEEFF}`. The braces are optional,
+    /// and the hex digits may be in any case.
+    fn from_str(s: &str) -> Result<Guid, ParseGuidError> {
+        let s = if s.starts_with('{') && s.ends_with('}') && s.len() >= 2 {
+            &s[1..s.len() - 1]
+        } else {
+            s
+        };
+
+        let groups: Vec<&str> = s.split('-').collect();
+        let group_lens = [8, 4, 4, 4, 12];
+        if groups.len() != group_lens.len() {
+            return Err(ParseGuidError);
+        }
+        for (group, &len) in groups.iter().zip(group_lens.iter()) {
+            if group.len() != len || !group.chars().all(|c| c.is_digit(16)) {
+                return Err(ParseGuidError);
+            }
+        }
+
+        let digits: String = groups.concat();
+        let mut bytes = [0; 16];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            // Can't fail, we checked the digits above.
+            *byte = u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).unwrap();
+        }
+        Ok(Guid::from_bytes(bytes))
+    }
+}
+
+impl std::fmt::Display for Guid {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        let b = &self.bytes;
+        write!(
+            f,
+            "{{{:02X}{:02X}{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}}}",
+            b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7],
+            b[8], b[9], b[10], b[11], b[12], b[13], b[14], b[15],
+        )
+    }
+}
+
+/// The convention of the ATR (Answer To Reset), as indicated by its
+/// initial character (TS).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Convention {
+    /// Direct convention (TS is `3B`).
+    Direct,
+    /// Inverse convention (TS is `3F`).
+    Inverse,
+}
+
+/// The channel through which a card reader is connected, as decoded
+/// from `Attribute::ChannelId`.
+///
+/// The channel numbers are interpreted as described in the PC/SC
+/// specification part 3, section 3.1.1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChannelId {
+    /// A serial port, with the port number.
+    Serial(u16),
+    /// A parallel port, with the port number.
+    Parallel(u16),
+    /// A PS/2 keyboard port.
+    Ps2Keyboard,
+    /// A SCSI bus, with the SCSI ID number.
+    Scsi(u16),
+    /// An IDE bus, with the device number.
+    Ide(u16),
+    /// A USB bus, with the device number. pcsclite's CCID driver encodes
+    /// the USB bus number in the high byte and the device address in the
+    /// low byte.
+    Usb(u16),
+    /// A vendor-defined channel, with the channel type (`F0` to `FF`) and
+    /// channel number.
+    Vendor(u16, u16),
+    /// A channel type not defined by the specification, with the channel
+    /// type and channel number.
+    Unknown(u16, u16),
+}
+
+impl ChannelId {
+    fn from_raw(raw: u32) -> ChannelId {
+        let channel_type = (raw >> 16) as u16;
+        let channel_number = raw as u16;
+        match channel_type {
+            0x01 => ChannelId::Serial(channel_number),
+            0x02 => ChannelId::Parallel(channel_number),
+            0x04 => ChannelId::Ps2Keyboard,
+            0x08 => ChannelId::Scsi(channel_number),
+            0x10 => ChannelId::Ide(channel_number),
+            0x20 => ChannelId::Usb(channel_number),
+            t if t & 0xFFF0 == 0x00F0 => ChannelId::Vendor(channel_type, channel_number),
+            _ => ChannelId::Unknown(channel_type, channel_number),
+        }
+    }
+}
+
 /// A class of Attributes.
 #[repr(u32)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -431,6 +820,89 @@ pub enum AttributeClass {
     System = ffi::SCARD_CLASS_SYSTEM as u32,
 }
 
+impl AttributeClass {
+    /// The attributes which belong to this class.
+    pub fn attributes(&self) -> &'static [Attribute] {
+        match *self {
+            AttributeClass::VendorInfo => &VENDOR_INFO_ATTRIBUTES,
+            AttributeClass::Communications => &COMMUNICATIONS_ATTRIBUTES,
+            AttributeClass::Protocol => &PROTOCOL_ATTRIBUTES,
+            AttributeClass::PowerMgmt => &POWER_MGMT_ATTRIBUTES,
+            AttributeClass::Security => &SECURITY_ATTRIBUTES,
+            AttributeClass::Mechanical => &MECHANICAL_ATTRIBUTES,
+            AttributeClass::VendorDefined => &VENDOR_DEFINED_ATTRIBUTES,
+            AttributeClass::IfdProtocol => &IFD_PROTOCOL_ATTRIBUTES,
+            AttributeClass::IccState => &ICC_STATE_ATTRIBUTES,
+            AttributeClass::System => &SYSTEM_ATTRIBUTES,
+        }
+    }
+}
+
+// The attributes of each class, as encoded in the high word of the
+// attribute tags (see `SCARD_ATTR_VALUE`).
+static VENDOR_INFO_ATTRIBUTES: [Attribute; 4] = [
+    Attribute::VendorName,
+    Attribute::VendorIfdType,
+    Attribute::VendorIfdVersion,
+    Attribute::VendorIfdSerialNo,
+];
+static COMMUNICATIONS_ATTRIBUTES: [Attribute; 1] = [
+    Attribute::ChannelId,
+];
+static PROTOCOL_ATTRIBUTES: [Attribute; 7] = [
+    Attribute::AsyncProtocolTypes,
+    Attribute::DefaultClk,
+    Attribute::MaxClk,
+    Attribute::DefaultDataRate,
+    Attribute::MaxDataRate,
+    Attribute::MaxIfsd,
+    Attribute::SyncProtocolTypes,
+];
+static POWER_MGMT_ATTRIBUTES: [Attribute; 1] = [
+    Attribute::PowerMgmtSupport,
+];
+static SECURITY_ATTRIBUTES: [Attribute; 2] = [
+    Attribute::UserToCardAuthDevice,
+    Attribute::UserAuthInputDevice,
+];
+static MECHANICAL_ATTRIBUTES: [Attribute; 1] = [
+    Attribute::Characteristics,
+];
+static VENDOR_DEFINED_ATTRIBUTES: [Attribute; 4] = [
+    Attribute::EscReset,
+    Attribute::EscCancel,
+    Attribute::EscAuthrequest,
+    Attribute::Maxinput,
+];
+static IFD_PROTOCOL_ATTRIBUTES: [Attribute; 12] = [
+    Attribute::CurrentProtocolType,
+    Attribute::CurrentClk,
+    Attribute::CurrentF,
+    Attribute::CurrentD,
+    Attribute::CurrentN,
+    Attribute::CurrentW,
+    Attribute::CurrentIfsc,
+    Attribute::CurrentIfsd,
+    Attribute::CurrentBwt,
+    Attribute::CurrentCwt,
+    Attribute::CurrentEbcEncoding,
+    Attribute::ExtendedBwt,
+];
+static ICC_STATE_ATTRIBUTES: [Attribute; 5] = [
+    Attribute::IccPresence,
+    Attribute::IccInterfaceStatus,
+    Attribute::CurrentIoState,
+    Attribute::AtrString,
+    Attribute::IccTypePerAtr,
+];
+static SYSTEM_ATTRIBUTES: [Attribute; 5] = [
+    Attribute::DeviceUnit,
+    Attribute::DeviceInUse,
+    Attribute::DeviceFriendlyName,
+    Attribute::DeviceSystemName,
+    Attribute::SupressT1IfsRequest,
+];
+
 /// Card reader attribute types.
 #[repr(u32)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -496,6 +968,9 @@ pub const MAX_BUFFER_SIZE: usize = ffi::MAX_BUFFER_SIZE;
 /// Maximum amount of bytes in an extended APDU command or response.
 pub const MAX_BUFFER_SIZE_EXTENDED: usize = ffi::MAX_BUFFER_SIZE_EXTENDED;
 
+// Size of the mandatory header (CLA INS P1 P2) of an APDU command.
+const APDU_HEADER_SIZE: usize = 4;
+
 /// A special reader name for detecting card reader insertions and removals.
 ///
 /// # Note
@@ -509,6 +984,208 @@ pub fn PNP_NOTIFICATION() -> &'static CStr {
     CStr::from_bytes_with_nul(b"\\\\?PnP?\\Notification\0").unwrap()
 }
 
+/// Check whether a reader name is the `PNP_NOTIFICATION()` pseudo-reader.
+///
+/// The pseudo-reader never has a card, so code handling reader events
+/// usually needs to treat it specially.
+pub fn is_pnp_notification(name: &CStr) -> bool {
+    name == PNP_NOTIFICATION()
+}
+
+/// Normalize a card reader name to a base name which is stable across
+/// platforms.
+///
+/// The same reader is named differently by each PC/SC implementation.
+/// For example, pcsclite may name a reader
+/// `ACS ACR122U PICC Interface 00 00`, while Windows names it
+/// `ACS ACR122U 0`. This function strips the trailing reader indexes
+/// and interface descriptions, returning `ACS ACR122U` for both.
+///
+/// The normalization is best-effort; since reader names are not
+/// standardized, it may not work for all readers, and distinct readers
+/// of the same model are normalized to the same name.
+pub fn normalize_reader_name(name: &CStr) -> Cow<str> {
+    fn trim_end(name: &str) -> &str {
+        let len = name.bytes().rposition(|c| c != b' ').map_or(0, |pos| pos + 1);
+        &name[..len]
+    }
+
+    // Split off the last space-separated token.
+    fn split_last(name: &str) -> Option<(&str, &str)> {
+        let name = trim_end(name);
+        name.rfind(' ').map(|pos| (trim_end(&name[..pos]), &name[pos + 1..]))
+    }
+
+    fn is_hex_byte(token: &str) -> bool {
+        token.len() == 2 && token.chars().all(|c| c.is_digit(16))
+    }
+
+    // Strip the reader index: "00 00" in pcsclite, "0" in Windows.
+    fn strip_index(name: &str) -> &str {
+        if let Some((rest, last)) = split_last(name) {
+            if let Some((rest2, prev)) = split_last(rest) {
+                if is_hex_byte(prev) && is_hex_byte(last) {
+                    return rest2;
+                }
+            }
+            if last.chars().all(|c| c.is_digit(10)) {
+                return rest;
+            }
+        }
+        name
+    }
+
+    // Strip an interface description, e.g. "[CCID Interface]" or
+    // "PICC Interface", or a serial number, e.g. "(0123456789)".
+    fn strip_interface(name: &str) -> Option<&str> {
+        let (rest, last) = match split_last(name) {
+            Some(split) => split,
+            None => return None,
+        };
+        if last.ends_with(']') {
+            return name.rfind('[').map(|pos| trim_end(&name[..pos]));
+        }
+        if last.ends_with(')') {
+            return name.rfind('(').map(|pos| trim_end(&name[..pos]));
+        }
+        if last == "Interface" {
+            return Some(split_last(rest).map_or(rest, |(rest, _)| rest));
+        }
+        None
+    }
+
+    let name = name.to_string_lossy();
+    let len = {
+        let mut base = strip_index(&name);
+        while let Some(stripped) = strip_interface(base) {
+            if stripped.is_empty() {
+                break;
+            }
+            base = stripped;
+        }
+        trim_end(base).len()
+    };
+
+    match name {
+        Cow::Borrowed(name) => Cow::Borrowed(&name[..len]),
+        Cow::Owned(mut name) => {
+            name.truncate(len);
+            Cow::Owned(name)
+        }
+    }
+}
+
+/// The interface of a card reader to the card.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReaderInterface {
+    /// A contact interface, including SAM slots.
+    Contact,
+    /// A contactless (proximity) interface.
+    Contactless,
+    /// The interface could not be determined.
+    Unknown,
+}
+
+/// Guess the interface of a card reader from its name.
+///
+/// Dual-interface readers usually present a separate reader for each
+/// interface, distinguished by markers in their names, for example
+/// `PICC` for the contactless interface and `ICC` or `SAM` for the
+/// contact interfaces.
+///
+/// The detection is heuristic; since reader names are not standardized,
+/// it may not work for all readers. If no known marker is found,
+/// `ReaderInterface::Unknown` is returned.
+pub fn reader_interface(name: &CStr) -> ReaderInterface {
+    let name = name.to_string_lossy().to_uppercase();
+    for word in name.split(|c: char| !c.is_alphanumeric()) {
+        if ["PICC", "CL", "CONTACTLESS"].contains(&word) {
+            return ReaderInterface::Contactless;
+        }
+        if ["ICC", "SAM", "CONTACT"].contains(&word) {
+            return ReaderInterface::Contact;
+        }
+    }
+    ReaderInterface::Unknown
+}
+
+/// Whether contexts of the PC/SC implementation may be shared across
+/// threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ThreadingModel {
+    /// A context may be used from multiple threads; operations on it are
+    /// serialized by the implementation.
+    SharedAllowed,
+    /// Each thread should use its own context.
+    ThreadLocal,
+}
+
+/// Get the threading model documented by the PC/SC implementation of the
+/// current platform.
+///
+/// pcsclite and WinSCard document that a context may be shared across
+/// threads, see the crate-level note on thread safety. The macOS PCSC
+/// framework does not, so each thread should establish its own context
+/// there.
+pub fn threading_model() -> ThreadingModel {
+    if cfg!(target_os = "macos") {
+        ThreadingModel::ThreadLocal
+    } else {
+        ThreadingModel::SharedAllowed
+    }
+}
+
+// The number of times `Context::list_readers_owned` tries to list the
+// readers, if the list grows while doing so.
+const LIST_READERS_OWNED_ATTEMPTS: usize = 3;
+
+// The timeout of each status change call in `wait_any`.
+const WAIT_ANY_POLL_INTERVAL_MS: u64 = 50;
+
+/// Wait for card and card reader state changes in any of multiple
+/// contexts.
+///
+/// Each entry of `contexts` is a context and the reader states to wait
+/// on in it, as would be passed to `Context::get_status_change`.
+///
+/// Returns the index of the first context which reported a change. Only
+/// the reader states of that context are updated.
+///
+/// If `timeout` elapses before any change is reported, `Error::Timeout`
+/// is returned.
+///
+/// ## Note
+///
+/// A single `SCardGetStatusChange` call cannot wait on multiple
+/// contexts, so this function polls: it calls `get_status_change` on
+/// each context in turn with a short timeout, until one of them reports
+/// a change. Changes are therefore noticed with some delay, and the
+/// function does not return immediately after `timeout` elapses.
+pub fn wait_any<D>(
+    contexts: &mut [(&Context, &mut [ReaderState])],
+    timeout: D,
+) -> Result<usize, Error>
+    where D: Into<Option<std::time::Duration>> {
+    let deadline = timeout.into().map(|timeout| std::time::Instant::now() + timeout);
+    let poll_interval = std::time::Duration::from_millis(WAIT_ANY_POLL_INTERVAL_MS);
+
+    loop {
+        for (index, &mut (context, ref mut readers)) in contexts.iter_mut().enumerate() {
+            match context.get_status_change(poll_interval, readers) {
+                Ok(()) => return Ok(index),
+                Err(Error::Timeout) => {},
+                Err(err) => return Err(err),
+            }
+        }
+
+        if let Some(deadline) = deadline {
+            if std::time::Instant::now() >= deadline {
+                return Err(Error::Timeout);
+            }
+        }
+    }
+}
+
 /// Transform a control code in the form expected by the platform.
 ///
 /// Control codes passed to `Card::control` are usually defined as inputs
@@ -544,64 +1221,377 @@ fn get_protocol_pci(protocol: Protocol) -> &'static ffi::SCARD_IO_REQUEST {
     }
 }
 
-struct ContextInner {
-    handle: ffi::SCARDCONTEXT,
-}
-
-/// Library context to the PCSC service.
+/// Get the protocol control information (PCI) structure of a protocol.
 ///
-/// This structure wraps `SCARDCONTEXT`.
-pub struct Context {
-    inner: Arc<ContextInner>,
-}
-
-/// A connection to a smart card.
+/// This is the structure which the `SCARD_PCI_T0`, `SCARD_PCI_T1` and
+/// `SCARD_PCI_RAW` defines of the C API point to, for use with raw
+/// `pcsc-sys` calls such as `SCardTransmit`.
 ///
-/// This structure wraps `SCARDHANDLE`.
-pub struct Card {
-    // Keeps the context alive.
-    _context: Context,
-    handle: ffi::SCARDHANDLE,
-    active_protocol: Protocol,
+/// This function is only available with the `ffi` feature.
+#[cfg(feature = "ffi")]
+pub fn protocol_pci(protocol: Protocol) -> &'static ffi::SCARD_IO_REQUEST {
+    get_protocol_pci(protocol)
 }
 
-/// An exclusive transaction with a card.
-///
-/// A transaction ensures uninterrupted access to the card for its
-/// duration. All other operations performed on the same underlying
-/// card (even from other processes) will block until the transaction
-/// is finished.
-// By taking a mut reference to the card we statically enforce that:
-// - There can only be one active transaction at a time.
-// - All operations on the card must be performed through the transaction
-//   for the duration of the transaction's lifetime.
-pub struct Transaction<'tx> {
-    card: &'tx mut Card,
+fn io_request_size() -> usize {
+    std::mem::size_of::<ffi::SCARD_IO_REQUEST>()
 }
 
-/// An iterator over card reader names.
+/// A buffer for receiving protocol control information (PCI) from
+/// `Card::transmit_with_pci`.
 ///
-/// The iterator does not perform any copying or allocations; this is left
-/// to the caller's discretion. It is therefore tied to the underlying
-/// buffer.
-#[derive(Clone)]
-pub struct ReaderNames<'buf> {
-    buf: &'buf [u8],
-    pos: usize,
+/// The receive PCI consists of an `SCARD_IO_REQUEST` header, followed by
+/// protocol-specific extra bytes. The buffer can be reused across
+/// transmits.
+pub struct RecvPci {
+    // Storage for the header and the extra bytes; DWORDs are used to get
+    // the alignment of the header.
+    buf: Vec<DWORD>,
+    extra_capacity: usize,
 }
 
-impl<'buf> Iterator for ReaderNames<'buf> {
-    type Item = &'buf CStr;
+impl RecvPci {
+    /// Create a receive PCI buffer with room for `extra_capacity` extra
+    /// bytes following the header.
+    pub fn new(extra_capacity: usize) -> RecvPci {
+        let dword_size = std::mem::size_of::<DWORD>();
+        // Rounded up.
+        let len = (io_request_size() + extra_capacity + dword_size - 1) / dword_size;
+        let mut recv_pci = RecvPci {
+            buf: vec![0; len],
+            extra_capacity,
+        };
+        recv_pci.reset();
+        recv_pci
+    }
 
-    fn next(&mut self) -> Option<&'buf CStr> {
-        match self.buf[self.pos..].iter().position(|&c| c == 0) {
-            None | Some(0) => None,
-            Some(len) => {
-                let old_pos = self.pos;
-                self.pos += len + 1;
-                // The panic can't happen, but we avoid unsafe.
-                Some(CStr::from_bytes_with_nul(&self.buf[old_pos..self.pos]).unwrap())
-            }
+    /// The protocol reported in the last transmit, if known.
+    pub fn protocol(&self) -> Option<Protocol> {
+        Protocol::from_raw_opt(self.header().dwProtocol)
+    }
+
+    /// The extra bytes received in the last transmit.
+    ///
+    /// The length is the PCI length reported by the reader
+    /// (`cbPciLength`) minus the size of the header, capped at the extra
+    /// capacity of the buffer.
+    pub fn extra_bytes(&self) -> &[u8] {
+        let len = (self.header().cbPciLength as usize)
+            .saturating_sub(io_request_size());
+        let len = std::cmp::min(len, self.extra_capacity);
+        unsafe {
+            let ptr = (self.buf.as_ptr() as *const u8).offset(io_request_size() as isize);
+            std::slice::from_raw_parts(ptr, len)
+        }
+    }
+
+    fn header(&self) -> &ffi::SCARD_IO_REQUEST {
+        unsafe { &*(self.buf.as_ptr() as *const ffi::SCARD_IO_REQUEST) }
+    }
+
+    fn header_mut(&mut self) -> &mut ffi::SCARD_IO_REQUEST {
+        unsafe { &mut *(self.buf.as_mut_ptr() as *mut ffi::SCARD_IO_REQUEST) }
+    }
+
+    // Prepare the buffer to be passed to SCardTransmit. The extra bytes
+    // are zeroed, so none are left over from a previous transmit.
+    fn reset(&mut self) {
+        for dword in self.buf.iter_mut() {
+            *dword = 0;
+        }
+        let pci_length = (io_request_size() + self.extra_capacity) as DWORD;
+        let header = self.header_mut();
+        header.dwProtocol = ffi::SCARD_PROTOCOL_UNDEFINED;
+        header.cbPciLength = pci_length;
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut ffi::SCARD_IO_REQUEST {
+        self.buf.as_mut_ptr() as *mut ffi::SCARD_IO_REQUEST
+    }
+}
+
+// Check that the protocol of a received PCI is the `expected` active
+// protocol. A PCI without a protocol is not considered mismatched.
+fn check_recv_pci_protocol(recv_pci: &RecvPci, expected: Option<Protocol>) -> Result<(), Error> {
+    match recv_pci.protocol() {
+        Some(protocol) if Some(protocol) != expected => Err(Error::ProtoMismatch),
+        _ => Ok(()),
+    }
+}
+
+/// A reusable buffer for receiving APDU responses and attributes.
+///
+/// The functions of `Scratch` grow the internal buffer as needed, and
+/// return a slice into it. Since the allocation is kept, reusing the same
+/// `Scratch` avoids allocating for each call, while not requiring the
+/// caller to size buffers.
+#[derive(Debug, Default)]
+pub struct Scratch {
+    buf: Vec<u8>,
+}
+
+impl Scratch {
+    /// Create an empty scratch buffer.
+    pub fn new() -> Scratch {
+        Scratch {
+            buf: Vec::new(),
+        }
+    }
+
+    /// Transmit an APDU command to the card, returning the APDU response.
+    ///
+    /// See `Card::transmit_into_vec`.
+    pub fn transmit(
+        &mut self,
+        card: &Card,
+        send_buffer: &[u8],
+    ) -> Result<&[u8], Error> {
+        card.transmit_into_vec(send_buffer, &mut self.buf)?;
+        Ok(&self.buf)
+    }
+
+    /// Get an attribute of the card or card reader.
+    ///
+    /// See `Card::get_attribute`.
+    pub fn get_attribute(
+        &mut self,
+        card: &Card,
+        attribute: Attribute,
+    ) -> Result<&[u8], Error> {
+        let len = card.get_attribute_len(attribute)?;
+        self.buf.clear();
+        self.buf.resize(len, 0);
+        let len = card.get_attribute(attribute, &mut self.buf)?.len();
+        self.buf.truncate(len);
+        Ok(&self.buf)
+    }
+}
+
+/// Details on a failure to reach the pcscd daemon.
+///
+/// See `Context::last_os_detail`.
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+#[derive(Debug, Clone)]
+pub struct OsErrorDetail {
+    os_error: Option<i32>,
+    socket_path: std::path::PathBuf,
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+thread_local! {
+    static LAST_OS_DETAIL: std::cell::RefCell<Option<OsErrorDetail>> = std::cell::RefCell::new(None);
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+impl OsErrorDetail {
+    /// The OS error encountered when connecting to the pcscd socket, if
+    /// any.
+    ///
+    /// For example, `ErrorKind::NotFound` if pcscd is not running, or
+    /// `ErrorKind::PermissionDenied` if the socket is not accessible.
+    pub fn os_error(&self) -> Option<std::io::Error> {
+        self.os_error.map(std::io::Error::from_raw_os_error)
+    }
+
+    /// The path of the pcscd socket.
+    pub fn socket_path(&self) -> &std::path::Path {
+        &self.socket_path
+    }
+
+    // Record the details of a failure of SCardEstablishContext.
+    fn record(err: LONG) {
+        let detail = if err == ffi::SCARD_E_NO_SERVICE {
+            // The errno left by pcsclite is not reliable, so retry the
+            // connection to get the actual error.
+            let os_error = std::io::Error::last_os_error().raw_os_error();
+            let socket_path = OsErrorDetail::pcscd_socket_path();
+            let os_error = match std::os::unix::net::UnixStream::connect(&socket_path) {
+                Ok(_) => os_error,
+                Err(err) => err.raw_os_error(),
+            };
+            Some(OsErrorDetail {
+                os_error,
+                socket_path,
+            })
+        } else {
+            None
+        };
+        LAST_OS_DETAIL.with(|last| *last.borrow_mut() = detail);
+    }
+
+    // Same logic as pcsclite's SYS_GetSockName.
+    fn pcscd_socket_path() -> std::path::PathBuf {
+        match std::env::var_os("PCSCLITE_CSOCK_NAME") {
+            Some(path) => path.into(),
+            None => "/run/pcscd/pcscd.comm".into(),
+        }
+    }
+}
+
+struct ContextInner {
+    handle: ffi::SCARDCONTEXT,
+    // The scope the context was established with.
+    scope: Scope,
+    // Whether the context was already released by `try_release`.
+    released: AtomicBool,
+}
+
+/// Library context to the PCSC service.
+///
+/// This structure wraps `SCARDCONTEXT`.
+pub struct Context {
+    inner: Arc<ContextInner>,
+}
+
+/// A handle for canceling blocking operations on a `Context`.
+///
+/// A `Canceler` does not keep the context alive. If the context was
+/// released, `cancel` fails with `Error::InvalidHandle`.
+#[derive(Clone)]
+pub struct Canceler {
+    inner: Weak<ContextInner>,
+}
+
+/// A connection to a smart card.
+///
+/// This structure wraps `SCARDHANDLE`.
+pub struct Card {
+    // Keeps the context alive.
+    _context: Context,
+    handle: ffi::SCARDHANDLE,
+    // The raw active protocol, kept up to date by `reconnect` and
+    // `status`. Undefined for direct connections.
+    active_protocol: AtomicUsize,
+    // The parameters of the last connect/reconnect, for resets.
+    share_mode: ShareMode,
+    preferred_protocols: Protocols,
+    // Cached result of `supports_transactions`, one of the
+    // TRANSACTIONS_* constants.
+    transactions_support: AtomicUsize,
+    // The number of `SCardTransmit` calls made with this card.
+    transmit_count: AtomicUsize,
+    // The receive buffer capacity of the owning transmit functions.
+    default_recv_capacity: usize,
+}
+
+const TRANSACTIONS_UNKNOWN: usize = 0;
+const TRANSACTIONS_SUPPORTED: usize = 1;
+const TRANSACTIONS_UNSUPPORTED: usize = 2;
+
+/// An exclusive transaction with a card.
+///
+/// A transaction ensures uninterrupted access to the card for its
+/// duration. All other operations performed on the same underlying
+/// card (even from other processes) will block until the transaction
+/// is finished.
+///
+/// Operations which reset or close the connection, like
+/// `Card::reconnect` and `Card::disconnect`, cannot be performed while a
+/// transaction is active; this is checked at compile time. For example,
+/// the following do not compile:
+///
+/// ```compile_fail
+/// # use pcsc::*;
+/// # fn f(card: &mut Card) {
+/// let tx = card.transaction().unwrap();
+/// tx.reconnect(ShareMode::Shared, Protocols::ANY, Disposition::ResetCard).unwrap();
+/// # }
+/// ```
+///
+/// ```compile_fail
+/// # use pcsc::*;
+/// # fn f(card: &mut Card) {
+/// let tx = card.transaction().unwrap();
+/// card.reconnect(ShareMode::Shared, Protocols::ANY, Disposition::ResetCard).unwrap();
+/// drop(tx);
+/// # }
+/// ```
+///
+/// ```compile_fail
+/// # use pcsc::*;
+/// # fn f(mut card: Card) {
+/// let tx = card.transaction().unwrap();
+/// let _ = card.disconnect(Disposition::ResetCard);
+/// drop(tx);
+/// # }
+/// ```
+///
+/// ## Card resets
+///
+/// If the card is reset by another application or by the reader during
+/// the transaction, the operations performed through the transaction fail
+/// with `Error::ResetCard`. Any state established on the card (selected
+/// applications, verified PINs, ...) is lost, and the transaction no
+/// longer guarantees exclusive access.
+///
+/// To recover, end the transaction, reconnect to the card with
+/// `Disposition::LeaveCard`, and restart the whole transaction from the
+/// beginning:
+///
+/// ```no_run
+/// # use pcsc::*;
+/// # fn f(card: &mut Card, share_mode: ShareMode) -> Result<(), Error> {
+/// loop {
+///     let result = card.transaction().and_then(|tx| {
+///         let mut buf = [0; MAX_BUFFER_SIZE];
+///         tx.transmit(b"\x00\xa4\x04\x00\x00", &mut buf).map(|_| ())
+///     });
+///     match result {
+///         Err(Error::ResetCard) => {
+///             card.reconnect(share_mode, Protocols::ANY, Disposition::LeaveCard)?;
+///         }
+///         result => return result,
+///     }
+/// }
+/// # }
+/// ```
+// By taking a mut reference to the card we statically enforce that:
+// - There can only be one active transaction at a time.
+// - All operations on the card must be performed through the transaction
+//   for the duration of the transaction's lifetime.
+// - The card cannot be reconnected or disconnected during the
+//   transaction, since `Transaction` only implements `Deref` (not
+//   `DerefMut`), and the card cannot be moved out while borrowed.
+pub struct Transaction<'tx> {
+    card: &'tx mut Card,
+}
+
+/// An iterator over card reader names.
+///
+/// The iterator does not perform any copying or allocations; this is left
+/// to the caller's discretion. It is therefore tied to the underlying
+/// buffer.
+///
+/// The names are expected to be NUL-terminated, with the list terminated
+/// by an empty name. If the buffer is malformed (e.g. the last name is not
+/// NUL-terminated), the iteration stops at the last well-formed name.
+#[derive(Clone)]
+pub struct ReaderNames<'buf> {
+    buf: &'buf [u8],
+    pos: usize,
+}
+
+impl<'buf> Iterator for ReaderNames<'buf> {
+    type Item = &'buf CStr;
+
+    fn next(&mut self) -> Option<&'buf CStr> {
+        // Be careful not to index out of bounds or loop on a malformed
+        // buffer, e.g. from a buggy driver.
+        let rest = match self.buf.get(self.pos..) {
+            Some(rest) => rest,
+            None => return None,
+        };
+        match rest.iter().position(|&c| c == 0) {
+            None | Some(0) => {
+                self.pos = self.buf.len();
+                None
+            },
+            Some(len) => {
+                let old_pos = self.pos;
+                self.pos += len + 1;
+                // The panic can't happen, but we avoid unsafe.
+                Some(CStr::from_bytes_with_nul(&self.buf[old_pos..self.pos]).unwrap())
+            }
         }
     }
 }
@@ -614,27 +1604,83 @@ impl Context {
     ///
     /// [1]: https://pcsclite.apdu.fr/api/group__API.html#gaa1b8970169fd4883a6dc4a8f43f19b67
     /// [2]: https://msdn.microsoft.com/en-us/library/aa379479.aspx
+    ///
+    /// ## Note
+    ///
+    /// If the PC/SC service is not running, this function fails with an
+    /// error for which `Error::is_daemon_unavailable` is true. With
+    /// pcsclite, if it fails with `Error::NoService`,
+    /// `Context::last_os_detail()` can be used to find out why the pcscd
+    /// daemon could not be reached.
     pub fn establish(
         scope: Scope,
     ) -> Result<Context, Error> {
         unsafe {
             let mut handle: ffi::SCARDCONTEXT = DUMMY_LONG as ffi::SCARDCONTEXT;
 
-            try_pcsc!(ffi::SCardEstablishContext(
+            let err = ffi::SCardEstablishContext(
                 scope.into_raw(),
                 null(),
                 null(),
                 &mut handle,
-            ));
+            );
+            #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+            OsErrorDetail::record(err);
+            try_pcsc!(err);
 
             Ok(Context {
                 inner: Arc::new(ContextInner {
                     handle,
+                    scope,
+                    released: AtomicBool::new(false),
                 }),
             })
         }
     }
 
+    /// Get details on the last failure to establish a context with
+    /// `Error::NoService` in the current thread.
+    ///
+    /// The details help telling apart the reasons pcscd could not be
+    /// reached, for example, pcscd not running or lack of permissions to
+    /// access its socket.
+    ///
+    /// Returns `None` if the last call to `Context::establish` in the
+    /// current thread did not fail with `Error::NoService`.
+    ///
+    /// This function is only available with pcsclite.
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    pub fn last_os_detail() -> Option<OsErrorDetail> {
+        LAST_OS_DETAIL.with(|detail| detail.borrow().clone())
+    }
+
+    /// Get the scope the context was established with.
+    pub fn scope(
+        &self,
+    ) -> Scope {
+        self.inner.scope
+    }
+
+    /// Establish a new context in place of this one, with the same
+    /// scope.
+    ///
+    /// This is useful to recover after the PC/SC service was restarted,
+    /// which invalidates existing contexts (operations fail with
+    /// `Error::ServiceStopped` or `Error::NoService`).
+    ///
+    /// The old context is released once it is no longer used. Note that
+    /// clones of the context, and cards connected through it, keep using
+    /// the old context.
+    ///
+    /// In case of error, the old context is kept.
+    pub fn reestablish(
+        &mut self,
+    ) -> Result<(), Error> {
+        *self = Context::establish(self.inner.scope)?;
+
+        Ok(())
+    }
+
     /// Release the context.
     ///
     /// In case of error, ownership of the context is returned to the
@@ -659,6 +1705,11 @@ impl Context {
     ) -> Result<(), (Context, Error)> {
         match Arc::try_unwrap(self.inner) {
             Ok(inner) => {
+                // Already released by try_release(); dropping is a no-op.
+                if inner.released.load(Ordering::SeqCst) {
+                    return Ok(());
+                }
+
                 unsafe {
                     let err = ffi::SCardReleaseContext(
                         inner.handle,
@@ -681,6 +1732,50 @@ impl Context {
         }
     }
 
+    /// Abandon the context without releasing it, returning the raw
+    /// `SCARDCONTEXT` handle.
+    ///
+    /// After this call, the context is never released by this library,
+    /// even if clones of the `Context` or `Card`s connected with it are
+    /// dropped. The caller becomes responsible for the handle.
+    pub fn leak(
+        self,
+    ) -> ffi::SCARDCONTEXT {
+        let handle = self.inner.handle;
+        // Leaks our reference, so the inner context is never dropped.
+        forget(self);
+        handle
+    }
+
+    /// Release the context, without consuming it.
+    ///
+    /// This function is like `release`, but on failure the context is
+    /// kept in place, so the release can be retried. On success, the
+    /// context is marked as released, and is not released again when
+    /// dropped; any further use of it fails.
+    ///
+    /// If the `Context` was cloned, and a clone is still alive (including
+    /// in a `Card`), this function fails with `Error::CantDispose`.
+    pub fn try_release(
+        &self,
+    ) -> Result<(), Error> {
+        if Arc::strong_count(&self.inner) > 1 {
+            return Err(Error::CantDispose);
+        }
+        if self.inner.released.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        unsafe {
+            try_pcsc!(ffi::SCardReleaseContext(
+                self.inner.handle,
+            ));
+        }
+
+        self.inner.released.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
     /// Check whether the Context is still valid.
     ///
     /// This function wraps `SCardIsValidContext` ([pcsclite][1],
@@ -720,6 +1815,18 @@ impl Context {
         }
     }
 
+    /// Get a `Canceler` for this context.
+    ///
+    /// The `Canceler` can be moved to another thread, and used to cancel
+    /// ongoing blocking operations in the context, like `cancel`.
+    pub fn canceler(
+        &self,
+    ) -> Canceler {
+        Canceler {
+            inner: Arc::downgrade(&self.inner),
+        }
+    }
+
     /// List all connected card readers.
     ///
     /// `buffer` is a buffer that should be large enough to hold all of
@@ -767,8 +1874,41 @@ impl Context {
         }
     }
 
+    /// List all connected card readers again, reusing a buffer.
+    ///
+    /// This function is the same as `list_readers`. It is meant for
+    /// refreshing the list of readers periodically (e.g. in a polling
+    /// UI) without allocating: the same `buffer` can be passed on each
+    /// refresh, and the reader names are yielded directly from it.
+    ///
+    /// Since the returned iterator borrows `buffer`, the iterator from
+    /// the previous refresh must be dropped before calling this function
+    /// again with the same buffer; the borrow checker enforces this.
+    pub fn refresh_into<'buf>(
+        &self,
+        buffer: &'buf mut [u8],
+    ) -> Result<ReaderNames<'buf>, Error> {
+        self.list_readers(buffer)
+    }
+
     /// Get the needed length of a buffer to be passed to `list_readers`.
     ///
+    /// A buffer of exactly this length is large enough for `list_readers`,
+    /// unless a reader is connected in between. If no readers are
+    /// available, `0` is returned rather than an error.
+    ///
+    /// ```no_run
+    /// # use pcsc::*;
+    /// # fn f(ctx: &Context) -> Result<(), Error> {
+    /// let len = ctx.list_readers_len()?;
+    /// let mut buf = vec![0; len];
+    /// for reader in ctx.list_readers(&mut buf)? {
+    ///     println!("{:?}", reader);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
     /// This function wraps `SCardListReaders` ([pcsclite][1], [MSDN][2]).
     ///
     /// [1]: https://pcsclite.apdu.fr/api/group__API.html#ga93b07815789b3cf2629d439ecf20f0d9
@@ -796,6 +1936,116 @@ impl Context {
         }
     }
 
+    /// List all connected card readers into newly allocated names.
+    ///
+    /// This is a convenience wrapper over `list_readers_len` and
+    /// `list_readers`. If a reader is connected between the two calls, so
+    /// that the buffer turns out to be too small, the required length is
+    /// queried again, a few times at most before `Error::InsufficientBuffer`
+    /// is returned.
+    pub fn list_readers_owned(
+        &self,
+    ) -> Result<Vec<CString>, Error> {
+        let mut attempts = 0;
+        loop {
+            let mut buffer = vec![0; self.list_readers_len()?];
+            match self.list_readers(&mut buffer) {
+                Ok(names) => return Ok(names.map(|name| name.to_owned()).collect()),
+                Err(Error::InsufficientBuffer) if attempts + 1 < LIST_READERS_OWNED_ATTEMPTS => {},
+                Err(err) => return Err(err),
+            }
+            attempts += 1;
+        }
+    }
+
+    /// List all smart card types known to the system.
+    ///
+    /// `buffer` is a buffer that should be large enough to hold all of
+    /// the card names.
+    ///
+    /// Returns an iterator over the card names. The iterator yields
+    /// values directly from `buffer`.
+    ///
+    /// If the buffer is not large enough to hold all of the names,
+    /// `Error::InsufficientBuffer` is returned.
+    ///
+    /// This function wraps `SCardListCards` ([MSDN][1]), without any ATR
+    /// or interface filter. It is only available on Windows; on other
+    /// platforms `Error::UnsupportedFeature` is returned.
+    ///
+    /// [1]: https://msdn.microsoft.com/en-us/library/aa379789.aspx
+    pub fn list_all_cards<'buf>(
+        &self,
+        buffer: &'buf mut [u8],
+    ) -> Result<ReaderNames<'buf>, Error> {
+        #[cfg(target_os = "windows")]
+        unsafe {
+            assert!(buffer.len() <= std::u32::MAX as usize);
+            let mut buflen = buffer.len() as DWORD;
+
+            try_pcsc!(ffi::SCardListCards(
+                self.inner.handle,
+                null(),
+                null(),
+                0,
+                buffer.as_mut_ptr() as *mut c_char,
+                &mut buflen,
+            ));
+
+            Ok(ReaderNames {
+                buf: &buffer[..buflen as usize],
+                pos: 0,
+            })
+        }
+
+        #[cfg(not(target_os = "windows"))]
+        {
+            let _ = buffer;
+            Err(Error::UnsupportedFeature)
+        }
+    }
+
+    /// Get the name of a provider registered for a smart card type.
+    ///
+    /// `card` is a card type name, as returned by `list_all_cards`.
+    ///
+    /// `buffer` is a buffer that should be large enough to hold the
+    /// provider name.
+    ///
+    /// If the buffer is not large enough to hold the name,
+    /// `Error::InsufficientBuffer` is returned.
+    ///
+    /// This function wraps `SCardGetCardTypeProviderName` ([MSDN][1]). It
+    /// is only available on Windows.
+    ///
+    /// [1]: https://msdn.microsoft.com/en-us/library/aa379766.aspx
+    #[cfg(target_os = "windows")]
+    pub fn card_type_provider_name<'buf>(
+        &self,
+        card: &CStr,
+        provider_id: ProviderId,
+        buffer: &'buf mut [u8],
+    ) -> Result<&'buf CStr, Error> {
+        unsafe {
+            assert!(buffer.len() <= std::u32::MAX as usize);
+            let mut buflen = buffer.len() as DWORD;
+
+            try_pcsc!(ffi::SCardGetCardTypeProviderName(
+                self.inner.handle,
+                card.as_ptr(),
+                provider_id.into_raw(),
+                buffer.as_mut_ptr() as *mut c_char,
+                &mut buflen,
+            ));
+
+            let buffer = &buffer[..buflen as usize];
+            match buffer.iter().position(|&c| c == 0) {
+                Some(nul_pos) => Ok(CStr::from_bytes_with_nul(&buffer[..nul_pos + 1]).unwrap()),
+                None => Err(Error::InsufficientBuffer),
+            }
+        }
+    }
+
     /// Connect to a card which is present in a reader.
     ///
     /// See the `connect.rs` example program.
@@ -823,27 +2073,174 @@ impl Context {
                 &mut raw_active_protocol,
             ));
 
-            let active_protocol = Protocol::from_raw(raw_active_protocol);
-
             Ok(Card {
                 _context: self.clone(),
                 handle,
-                active_protocol,
+                active_protocol: AtomicUsize::new(raw_active_protocol as usize),
+                share_mode,
+                preferred_protocols,
+                transactions_support: AtomicUsize::new(TRANSACTIONS_UNKNOWN),
+                transmit_count: AtomicUsize::new(0),
+                default_recv_capacity: MAX_BUFFER_SIZE_EXTENDED,
             })
         }
     }
 
-    /// Wait for card and card reader state changes.
+    /// Connect to a card which is present in a reader, categorizing the
+    /// common failures.
     ///
-    /// The function blocks until the state of one of the readers changes
-    /// from corresponding passed-in `ReaderState`. The `ReaderState`s are
-    /// updated to report the new state.
+    /// This function is like `connect`, but errors are categorized in a
+    /// `ConnectError`, which makes it easier to tell a missing card from
+    /// a missing reader, e.g. when prompting the user to insert a card.
+    pub fn try_connect(
+        &self,
+        reader: &CStr,
+        share_mode: ShareMode,
+        preferred_protocols: Protocols,
+    ) -> Result<Card, ConnectError> {
+        Ok(self.connect(reader, share_mode, preferred_protocols)?)
+    }
+
+    /// Connect to a card, trying protocols in order of preference.
     ///
-    /// A special reader name, `\\?PnP?\Notification`, can be used to
-    /// detect card reader insertions and removals, as opposed to state
-    /// changes of a specific reader. Use `PNP_NOTIFICATION()` to easily
+    /// `Protocols` is an unordered mask, so with `connect` the PC/SC
+    /// implementation chooses among the preferred protocols. This function
+    /// instead tries to connect with each protocol of `order` in turn,
+    /// until one succeeds.
+    ///
+    /// Returns the card along with the protocol which was used.
+    ///
+    /// Only failures due to the protocol (`Error::ProtoMismatch`) cause
+    /// the next protocol to be tried; other errors are returned
+    /// immediately. If no protocol succeeds, the last
+    /// error is returned. If `order` is empty, `Error::InvalidParameter`
+    /// is returned.
+    pub fn connect_preferring(
+        &self,
+        reader: &CStr,
+        share_mode: ShareMode,
+        order: &[Protocol],
+    ) -> Result<(Card, Protocol), Error> {
+        let mut last_err = Error::InvalidParameter;
+        for &protocol in order {
+            let protocols = Protocols::from_bits_truncate(DWORD::from(protocol as u32));
+            match self.connect(reader, share_mode, protocols) {
+                Ok(card) => return Ok((card, protocol)),
+                Err(Error::ProtoMismatch) => last_err = Error::ProtoMismatch,
+                Err(err) => return Err(err),
+            }
+        }
+        Err(last_err)
+    }
+
+    /// Connect to a card in the first reader whose name contains `substr`.
+    ///
+    /// The readers are listed with `list_readers`, and the first reader
+    /// whose name is valid UTF-8 and contains `substr` is connected to.
+    /// Other matching readers are not tried if connecting fails.
+    ///
+    /// Returns the name of the reader along with the card.
+    ///
+    /// If no reader matches, `Error::UnknownReader` is returned.
+    pub fn connect_matching(
+        &self,
+        substr: &str,
+        share_mode: ShareMode,
+        preferred_protocols: Protocols,
+    ) -> Result<(CString, Card), Error> {
+        let mut readers_buf = vec![0; self.list_readers_len()?];
+        let reader = {
+            let mut readers = self.list_readers(&mut readers_buf)?;
+            let matching = readers.find(|name| {
+                match name.to_str() {
+                    Ok(name) => name.contains(substr),
+                    Err(_) => false,
+                }
+            });
+            match matching {
+                Some(reader) => reader.to_owned(),
+                None => return Err(Error::UnknownReader),
+            }
+        };
+        let card = self.connect(&reader, share_mode, preferred_protocols)?;
+        Ok((reader, card))
+    }
+
+    /// List the card readers which are SAM (Secure Access Module) slots.
+    ///
+    /// Readers with SAM slots usually present each slot as a separate
+    /// reader, with `SAM` as a word in its name (e.g.
+    /// `ACS ACR1281 1S Dual Reader SAM 00 00`). This function returns the
+    /// readers with such names; as with `reader_interface`, the detection
+    /// is heuristic.
+    pub fn sam_readers(
+        &self,
+    ) -> Result<Vec<CString>, Error> {
+        let mut readers_buf = vec![0; self.list_readers_len()?];
+        let readers = self.list_readers(&mut readers_buf)?;
+        Ok(readers
+            .filter(|name| {
+                let name = name.to_string_lossy().to_uppercase();
+                let is_sam = name.split(|c: char| !c.is_alphanumeric()).any(|word| word == "SAM");
+                is_sam
+            })
+            .map(|name| name.to_owned())
+            .collect())
+    }
+
+    /// Connect to a card, run `f` with the connection, and disconnect.
+    ///
+    /// The card is disconnected using `disposition`, even if `f` fails.
+    /// If `f` fails, its error is returned, and an error in disconnecting
+    /// is ignored; otherwise, an error in disconnecting is returned.
+    pub fn with_card<T, F>(
+        &self,
+        reader: &CStr,
+        share_mode: ShareMode,
+        preferred_protocols: Protocols,
+        disposition: Disposition,
+        f: F,
+    ) -> Result<T, Error>
+        where F: FnOnce(&mut Card) -> Result<T, Error> {
+        let mut card = self.connect(reader, share_mode, preferred_protocols)?;
+        let result = f(&mut card);
+        let disconnect_result = card.disconnect(disposition);
+        let value = result?;
+        disconnect_result.map_err(|(_, err)| err)?;
+        Ok(value)
+    }
+
+    /// Wait for card and card reader state changes.
+    ///
+    /// The function blocks until the state of one of the readers changes
+    /// from corresponding passed-in `ReaderState`. The `ReaderState`s are
+    /// updated to report the new state.
+    ///
+    /// A special reader name, `\\?PnP?\Notification`, can be used to
+    /// detect card reader insertions and removals, as opposed to state
+    /// changes of a specific reader. Use `PNP_NOTIFICATION()` to easily
     /// obtain a static reference to this name.
     ///
+    /// A reader which is unknown or became unavailable is not an error;
+    /// it is reported with `State::UNKNOWN` or `State::UNAVAILABLE` in
+    /// the event state of the corresponding `ReaderState`, along with
+    /// `State::CHANGED`. The other readers are reported as usual:
+    ///
+    /// ```no_run
+    /// # use pcsc::*;
+    /// # use std::ffi::CString;
+    /// # fn f(ctx: &Context, live_reader: &std::ffi::CStr) -> Result<(), Error> {
+    /// let mut readers = vec![
+    ///     ReaderState::new(live_reader, State::UNAWARE),
+    ///     ReaderState::new(CString::new("No such reader").unwrap(), State::UNAWARE),
+    /// ];
+    /// ctx.get_status_change(None, &mut readers)?;
+    /// assert!(!readers[0].event_state().contains(State::UNKNOWN));
+    /// assert!(readers[1].event_state().contains(State::CHANGED | State::UNKNOWN));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
     /// See the `monitor.rs` example program.
     ///
     /// This function wraps `SCardGetStatusChange` ([pcsclite][1],
@@ -867,23 +2264,325 @@ impl Context {
             None => ffi::INFINITE
         };
 
+        // Clear CHANGED, so that it is only set below if reported by this
+        // call, rather than left over from a previous one.
+        for rs in readers.iter_mut() {
+            rs.inner.dwEventState &= !State::CHANGED.bits();
+        }
+
         unsafe {
             assert!(readers.len() <= std::u32::MAX as usize);
 
-            try_pcsc!(ffi::SCardGetStatusChange(
+            let err = ffi::SCardGetStatusChange(
                 self.inner.handle,
                 timeout_ms,
                 readers.as_mut_ptr() as *mut ffi::SCARD_READERSTATE,
                 readers.len() as DWORD,
-            ));
+            );
+            if err == ffi::SCARD_S_SUCCESS {
+                return Ok(());
+            }
 
-            Ok(())
+            // Some implementations fail the entire call if one of the
+            // readers is unknown or unavailable, even though this is
+            // already reported in the state of the reader. This is not a
+            // failure of the call, so don't report it as one. CHANGED was
+            // cleared above, so a stale state does not hide a failure.
+            let err = Error::from_raw(err);
+            if err == Error::UnknownReader || err == Error::ReaderUnavailable {
+                let reported = readers.iter().any(|rs| {
+                    let state = rs.event_state();
+                    state.contains(State::CHANGED)
+                        && state.intersects(State::UNKNOWN | State::UNAVAILABLE)
+                });
+                if reported {
+                    return Ok(());
+                }
+            }
+
+            Err(err)
+        }
+    }
+
+    /// Wait for card and card reader state changes, removing readers
+    /// which no longer exist.
+    ///
+    /// This function is like `get_status_change`, but afterwards removes
+    /// from `readers` all entries whose event state is `State::UNKNOWN`
+    /// or `State::IGNORE`. This avoids repeatedly waking up on readers
+    /// which were disconnected.
+    ///
+    /// Returns the names of the removed readers.
+    pub fn get_status_change_pruning<D>(
+        &self,
+        timeout: D,
+        readers: &mut Vec<ReaderState>,
+    ) -> Result<Vec<CString>, Error>
+        where D: Into<Option<std::time::Duration>> {
+        self.get_status_change(timeout, readers)?;
+
+        let mut removed = Vec::new();
+        readers.retain(|rs| {
+            let dead = rs.event_state().intersects(State::UNKNOWN | State::IGNORE);
+            if dead {
+                removed.push(rs.name().to_owned());
+            }
+            !dead
+        });
+
+        Ok(removed)
+    }
+
+    /// List all connected card readers together with the ATR of the card
+    /// inserted in each of them.
+    ///
+    /// The ATR is `None` if the reader is empty.
+    ///
+    /// This is a convenience wrapper over `list_readers` and a
+    /// non-blocking `get_status_change`; no connection to the cards is
+    /// made.
+    pub fn readers_with_atr(
+        &self,
+    ) -> Result<Vec<(CString, Option<Vec<u8>>)>, Error> {
+        Ok(self.enumerate()?.into_iter().map(|info| (info.name, info.atr)).collect())
+    }
+
+    /// List all connected card readers together with the availability of
+    /// the card inserted in each of them.
+    ///
+    /// This allows finding out which readers can be connected to, without
+    /// attempting the connection. The availability is derived from the
+    /// state flags returned by a non-blocking `get_status_change`; note
+    /// that it may change at any time.
+    pub fn readers_availability(
+        &self,
+    ) -> Result<Vec<(CString, Availability)>, Error> {
+        Ok(self.enumerate()?.into_iter().map(|info| {
+            let state = info.state;
+            let availability = if state.contains(State::EMPTY) || !state.contains(State::PRESENT) {
+                Availability::Empty
+            } else if state.contains(State::EXCLUSIVE) {
+                Availability::Exclusive
+            } else if state.contains(State::INUSE) {
+                Availability::Shared
+            } else {
+                Availability::Free
+            };
+            (info.name, availability)
+        }).collect())
+    }
+
+    /// Take a snapshot of all connected card readers and their state.
+    ///
+    /// Each snapshot holds the name of the reader, its current state and
+    /// the ATR of the inserted card, if any.
+    ///
+    /// This is a convenience wrapper over `list_readers` and a
+    /// non-blocking `get_status_change`; no connection to the cards is
+    /// made.
+    pub fn enumerate(
+        &self,
+    ) -> Result<Vec<ReaderInfoSnapshot>, Error> {
+        let mut readers_buf = vec![0; self.list_readers_len()?];
+        let mut reader_states = self.list_readers(&mut readers_buf)?
+            .map(|name| ReaderState::new(name, State::UNAWARE))
+            .collect::<Vec<_>>();
+        if reader_states.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        match self.get_status_change(std::time::Duration::from_secs(0), &mut reader_states) {
+            Ok(()) | Err(Error::Timeout) => (),
+            Err(err) => return Err(err),
+        }
+
+        Ok(reader_states.iter().map(|rs| {
+            let state = rs.event_state();
+            let atr = if state.contains(State::PRESENT) {
+                Some(rs.atr().to_vec())
+            } else {
+                None
+            };
+            ReaderInfoSnapshot {
+                name: rs.name().to_owned(),
+                state,
+                atr,
+            }
+        }).collect())
+    }
+
+    /// Get a value which changes whenever the list of card readers
+    /// changes.
+    ///
+    /// The value is the event count of the `PNP_NOTIFICATION()` reader,
+    /// queried with a single call to `get_status_change` with a zero
+    /// timeout. An application can cache the reader list, and only list
+    /// the readers again when this value changes.
+    ///
+    /// The value should only be compared for equality. Its meaning
+    /// differs between PC/SC implementations; for example, Windows counts
+    /// the changes, while pcsclite reports the number of readers.
+    ///
+    /// ## Note
+    ///
+    /// Since pcsclite reports the number of readers, the value does not
+    /// change when a reader is replaced by another between two calls
+    /// (one disconnected and another connected). To be notified of every
+    /// change, use `ReaderSet::poll`, or wait on the `PNP_NOTIFICATION()`
+    /// reader with `get_status_change`.
+    pub fn reader_list_generation(
+        &self,
+    ) -> Result<u32, Error> {
+        let mut reader_states = [ReaderState::new(PNP_NOTIFICATION(), State::UNAWARE)];
+        match self.get_status_change(std::time::Duration::from_secs(0), &mut reader_states) {
+            Ok(()) | Err(Error::Timeout) => (),
+            Err(err) => return Err(err),
+        }
+
+        Ok(reader_states[0].event_count())
+    }
+
+    /// Wait until a card reader is added or removed.
+    ///
+    /// The current state of the `PNP_NOTIFICATION()` reader is queried,
+    /// and then waited on with `get_status_change`, taking care of the
+    /// event count. Changes which happen before this function is called
+    /// are not reported.
+    ///
+    /// Returns `true` if the list of readers changed, or `false` if
+    /// `timeout` elapsed first. If the PC/SC implementation does not
+    /// support `PNP_NOTIFICATION()`, `Error::UnsupportedFeature` is
+    /// returned.
+    pub fn wait_reader_list_change<D>(
+        &self,
+        timeout: D,
+    ) -> Result<bool, Error>
+        where D: Into<Option<std::time::Duration>> {
+        let mut reader_states = [ReaderState::new(PNP_NOTIFICATION(), State::UNAWARE)];
+        match self.get_status_change(std::time::Duration::from_secs(0), &mut reader_states) {
+            Ok(()) | Err(Error::Timeout) => (),
+            Err(err) => return Err(err),
+        }
+        // Without support for PNP_NOTIFICATION(), waiting would return
+        // immediately every time.
+        if reader_states[0].event_state().intersects(State::UNKNOWN | State::IGNORE) {
+            return Err(Error::UnsupportedFeature);
+        }
+        reader_states[0].sync_current_state();
+
+        match self.get_status_change(timeout, &mut reader_states) {
+            Ok(()) => Ok(true),
+            Err(Error::Timeout) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Check whether a card is present in a reader, without connecting
+    /// to it.
+    ///
+    /// The state of the reader is queried with a single call to
+    /// `get_status_change` with a zero timeout. This is lightweight, and
+    /// does not disturb other connections to the card, so it is suitable
+    /// for frequent polling.
+    ///
+    /// If the reader does not exist, `Error::UnknownReader` is returned.
+    pub fn card_present(
+        &self,
+        reader: &CStr,
+    ) -> Result<bool, Error> {
+        let mut reader_states = [ReaderState::new(reader, State::UNAWARE)];
+        match self.get_status_change(std::time::Duration::from_secs(0), &mut reader_states) {
+            Ok(()) | Err(Error::Timeout) => (),
+            Err(err) => return Err(err),
+        }
+
+        let state = reader_states[0].event_state();
+        if state.contains(State::UNKNOWN) {
+            return Err(Error::UnknownReader);
+        }
+        Ok(state.contains(State::PRESENT))
+    }
+
+    /// Wait until a card with a matching ATR (Answer To Reset) is present
+    /// in a reader.
+    ///
+    /// The ATR of the card must have the same length as `atr`, and be
+    /// equal to it on all the bits set in `mask`, as in `AtrRegistry`. If
+    /// a matching card is already present, this returns immediately.
+    ///
+    /// If `atr` and `mask` do not have the same length,
+    /// `Error::InvalidParameter` is returned. If the reader does not
+    /// exist or is removed, `Error::UnknownReader` is returned. If
+    /// `timeout` elapses before a matching card is inserted,
+    /// `Error::Timeout` is returned.
+    ///
+    /// ## Note
+    ///
+    /// The matching is done by this function rather than with
+    /// `State::ATRMATCH`; pcsclite does not report it, and WinSCard
+    /// only reports it from `SCardLocateCardsByATR`.
+    pub fn wait_for_atr<D>(
+        &self,
+        reader: &CStr,
+        atr: &[u8],
+        mask: &[u8],
+        timeout: D,
+    ) -> Result<(), Error>
+        where D: Into<Option<std::time::Duration>> {
+        if atr.len() != mask.len() {
+            return Err(Error::InvalidParameter);
+        }
+        let deadline = timeout.into().map(|timeout| std::time::Instant::now() + timeout);
+
+        let mut reader_states = [ReaderState::new(reader, State::UNAWARE)];
+        loop {
+            let remaining = match deadline {
+                Some(deadline) => {
+                    let now = std::time::Instant::now();
+                    if now >= deadline {
+                        return Err(Error::Timeout);
+                    }
+                    Some(deadline - now)
+                },
+                None => None,
+            };
+            self.get_status_change(remaining, &mut reader_states)?;
+
+            let state = reader_states[0].event_state();
+            if state.intersects(State::UNKNOWN | State::IGNORE) {
+                return Err(Error::UnknownReader);
+            }
+            if state.contains(State::PRESENT) && atr_matches(reader_states[0].atr(), atr, mask) {
+                return Ok(());
+            }
+            reader_states[0].sync_current_state();
+        }
+    }
+}
+
+impl Canceler {
+    /// Cancel any ongoing blocking operation in the context.
+    ///
+    /// This function wraps `SCardCancel` ([pcsclite][1], [MSDN][2]).
+    ///
+    /// [1]: https://pcsclite.apdu.fr/api/group__API.html#gaacbbc0c6d6c0cbbeb4f4debf6fbeeee6
+    /// [2]: https://msdn.microsoft.com/en-us/library/aa379470.aspx
+    pub fn cancel(
+        &self,
+    ) -> Result<(), Error> {
+        match self.inner.upgrade() {
+            Some(inner) => Context { inner }.cancel(),
+            None => Err(Error::InvalidHandle),
         }
     }
 }
 
 impl Drop for ContextInner {
     fn drop(&mut self) {
+        if self.released.load(Ordering::SeqCst) {
+            return;
+        }
+
         unsafe {
             // Error is ignored here; to do proper error handling,
             // release() should be called manually.
@@ -937,6 +2636,13 @@ impl ReaderState {
         unsafe { CStr::from_ptr(self.inner.szReader) }
     }
 
+    /// Check whether this is the state of the card reader named `name`.
+    ///
+    /// This also works for the special `PNP_NOTIFICATION()` name.
+    pub fn has_name(&self, name: &CStr) -> bool {
+        self.name() == name
+    }
+
     /// The ATR (Answer To Reset) of the card inserted to the reader.
     pub fn atr(&self) -> &[u8] {
         &self.inner.rgbAtr[0..self.inner.cbAtr as usize]
@@ -963,260 +2669,2250 @@ impl ReaderState {
         // https://stackoverflow.com/a/16467368
         self.inner.dwCurrentState = self.inner.dwEventState;
     }
-}
 
-impl Drop for ReaderState {
-    fn drop(&mut self) {
-        // Reclaim the name and drop it immediately.
-        unsafe { CString::from_raw(self.inner.szReader as *mut c_char) };
+    /// Sync the currently-known state to the last reported state, and
+    /// return whether it changed.
+    ///
+    /// This is like `sync_current_state`, but returns `false` if the
+    /// currently-known state was already the same as the last reported
+    /// state (including the event count), so there is nothing new to
+    /// process.
+    pub fn sync_current_state_changed(&mut self) -> bool {
+        let changed = self.inner.dwCurrentState != self.inner.dwEventState;
+        self.sync_current_state();
+        changed
     }
-}
 
-impl Card {
-    /// Start a new exclusive transaction with the card.
-    ///
-    /// Operations on the card for the duration of the transaction
-    /// can only be performed through the returned `Transaction`.
+    /// Reset the state to `State::UNAWARE`, as if newly created.
     ///
-    /// This function wraps `SCardBeginTransaction` ([pcsclite][1],
-    /// [MSDN][2]).
-    ///
-    /// [1]: https://pcsclite.apdu.fr/api/group__API.html#gaddb835dce01a0da1d6ca02d33ee7d861
-    /// [2]: https://msdn.microsoft.com/en-us/library/aa379469.aspx
-    pub fn transaction(
-        &mut self,
-    ) -> Result<Transaction, Error> {
-        unsafe {
-            try_pcsc!(ffi::SCardBeginTransaction(
-                self.handle,
-            ));
+    /// Both the currently-known state and the last reported state are
+    /// reset, and the ATR is cleared. This is useful to re-baseline the
+    /// monitoring after the state became stale, for example following a
+    /// restart of the PC/SC service.
+    pub fn reset(&mut self) {
+        self.inner.dwCurrentState = State::UNAWARE.bits();
+        self.inner.dwEventState = State::UNAWARE.bits();
+        self.inner.cbAtr = 0;
+        self.inner.rgbAtr = [0; ffi::ATR_BUFFER_SIZE];
+    }
 
-            Ok(Transaction {
-                card: self,
-            })
+    /// Reset all of `readers` to `State::UNAWARE`.
+    ///
+    /// See `reset`.
+    pub fn reset_all(readers: &mut [ReaderState]) {
+        for rs in readers {
+            rs.reset();
         }
     }
 
-    /// Reconnect to the card.
-    ///
-    /// This function wraps `SCardReconnect` ([pcsclite][1], [MSDN][2]).
+    /// Split the names of `readers` into those with a card present and
+    /// those without, according to their event state.
     ///
-    /// [1]: https://pcsclite.apdu.fr/api/group__API.html#gad5d4393ca8c470112ad9468c44ed8940
-    /// [2]: https://msdn.microsoft.com/en-us/library/aa379797.aspx
-    pub fn reconnect(
-        &mut self,
-        share_mode: ShareMode,
-        preferred_protocols: Protocols,
-        initialization: Disposition,
-    ) -> Result<(), Error> {
-        unsafe {
-            let mut raw_active_protocol: DWORD = DUMMY_DWORD;
-
-            try_pcsc!(ffi::SCardReconnect(
-                self.handle,
-                share_mode.into_raw(),
-                preferred_protocols.bits(),
-                initialization.into_raw(),
-                &mut raw_active_protocol,
-            ));
+    /// Returns `(present, absent)`. A reader is present if its event
+    /// state contains `State::PRESENT`.
+    pub fn partition_present(readers: &[ReaderState]) -> (Vec<&CStr>, Vec<&CStr>) {
+        let mut present = Vec::new();
+        let mut absent = Vec::new();
+        for rs in readers {
+            if rs.event_state().contains(State::PRESENT) {
+                present.push(rs.name());
+            } else {
+                absent.push(rs.name());
+            }
+        }
+        (present, absent)
+    }
+}
 
-            self.active_protocol = Protocol::from_raw(raw_active_protocol);
+/// A collection of `ReaderState`s for monitoring a changing set of card
+/// readers.
+///
+/// Newly added readers start in `State::UNAWARE`, so their state is
+/// reported by the next call to `Context::get_status_change`, while
+/// readers whose state was synced with `sync_all` only report actual
+/// changes.
+///
+/// The collection dereferences to a slice of `ReaderState`s, and can be
+/// passed directly to `Context::get_status_change`.
+#[derive(Default)]
+pub struct ReaderStates {
+    states: Vec<ReaderState>,
+}
 
-            Ok(())
+impl ReaderStates {
+    /// Create an empty collection.
+    pub fn new() -> ReaderStates {
+        ReaderStates {
+            states: Vec::new(),
         }
     }
 
-    /// Disconnect from the card.
-    ///
-    /// In case of error, ownership of the card is returned to the caller.
-    ///
-    /// This function wraps `SCardDisconnect` ([pcsclite][1], [MSDN][2]).
+    /// Add a card reader to the collection, in `State::UNAWARE`.
     ///
-    /// [1]: https://pcsclite.apdu.fr/api/group__API.html#ga4be198045c73ec0deb79e66c0ca1738a
-    /// [2]: https://msdn.microsoft.com/en-us/library/aa379475.aspx
-    ///
-    /// ## Note
+    /// Returns `false` if a reader of this name is already in the
+    /// collection, in which case it is left unchanged.
+    pub fn add<T: Into<CString>>(
+        &mut self,
+        name: T,
+    ) -> bool {
+        let name = name.into();
+        if self.states.iter().any(|rs| rs.name() == &*name) {
+            return false;
+        }
+        self.states.push(ReaderState::new(name, State::UNAWARE));
+        true
+    }
+
+    /// Remove a card reader from the collection.
     ///
-    /// `Card` implements `Drop` which automatically disconnects the card
-    /// using `Disposition::ResetCard`; you only need to call this
-    /// function if you want to handle errors or use a different
-    /// disposition method.
-    pub fn disconnect(
-        mut self,
-        disposition: Disposition,
-    ) -> Result<(), (Card, Error)> {
-        unsafe {
-            let err = ffi::SCardDisconnect(
-                self.handle,
-                disposition.into_raw(),
-            );
-            if err != ffi::SCARD_S_SUCCESS {
-                return Err((self, Error::from_raw(err)));
+    /// Returns `false` if there is no reader of this name in the
+    /// collection.
+    pub fn remove(
+        &mut self,
+        name: &CStr,
+    ) -> bool {
+        let pos = self.states.iter().position(|rs| rs.name() == name);
+        match pos {
+            Some(pos) => {
+                self.states.remove(pos);
+                true
             }
-
-            // Skip the drop, we did it "manually".
-            std::ptr::drop_in_place(&mut self._context);
-            forget(self);
-
-            Ok(())
+            None => false,
         }
     }
 
-    /// Get current info on the card.
+    /// Sync the currently-known state of all readers to their last
+    /// reported state.
     ///
-    /// This function wraps `SCardStatus` ([pcsclite][1], [MSDN][2]).
-    ///
-    /// [1]: https://pcsclite.apdu.fr/api/group__API.html#gae49c3c894ad7ac12a5b896bde70d0382
-    /// [2]: https://msdn.microsoft.com/en-us/library/aa379803.aspx
-    // TODO: Missing return values: reader names and ATR.
-    pub fn status(
-        &self,
-    ) -> Result<(Status, Protocol), Error> {
+    /// This should be called after each call to
+    /// `Context::get_status_change`, before waiting again.
+    pub fn sync_all(&mut self) {
+        for rs in &mut self.states {
+            rs.sync_current_state();
+        }
+    }
+}
+
+impl Deref for ReaderStates {
+    type Target = [ReaderState];
+
+    fn deref(&self) -> &[ReaderState] {
+        &self.states
+    }
+}
+
+impl DerefMut for ReaderStates {
+    fn deref_mut(&mut self) -> &mut [ReaderState] {
+        &mut self.states
+    }
+}
+
+/// A change reported by `ReaderSet::poll`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ReaderEvent {
+    /// A card reader was connected.
+    ReaderAdded(CString),
+    /// A card reader was disconnected.
+    ReaderRemoved(CString),
+    /// A card was inserted to a card reader, with the given ATR.
+    CardInserted(CString, Vec<u8>),
+    /// A card was removed from a card reader.
+    CardRemoved(CString),
+}
+
+/// A monitor of all the card readers of the system and their cards.
+///
+/// The set tracks the `PNP_NOTIFICATION()` reader to find out when card
+/// readers are connected or disconnected, and the state of each card
+/// reader to find out when cards are inserted or removed.
+///
+/// On the first call to `poll`, all the connected readers are reported
+/// as added; their cards are reported as inserted on the next call.
+pub struct ReaderSet {
+    // The first state is that of PNP_NOTIFICATION().
+    states: Vec<ReaderState>,
+}
+
+impl ReaderSet {
+    /// Create a set with no known card readers.
+    pub fn new() -> ReaderSet {
+        ReaderSet {
+            states: vec![ReaderState::new(PNP_NOTIFICATION(), State::UNAWARE)],
+        }
+    }
+
+    /// The states of the known card readers, as of the last `poll`.
+    pub fn states(&self) -> &[ReaderState] {
+        &self.states[1..]
+    }
+
+    /// Wait for changes to the card readers or their cards, and return
+    /// them.
+    ///
+    /// This wraps `Context::get_status_change`, syncing the states of the
+    /// readers and refreshing the list of readers as needed. If `timeout`
+    /// elapses without any change, an empty list is returned.
+    pub fn poll<D>(
+        &mut self,
+        context: &Context,
+        timeout: D,
+    ) -> Result<Vec<ReaderEvent>, Error>
+        where D: Into<Option<std::time::Duration>> {
+        match context.get_status_change(timeout, &mut self.states) {
+            Ok(()) => (),
+            Err(Error::Timeout) => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        }
+
+        let mut events = Vec::new();
+        let mut i = 1;
+        while i < self.states.len() {
+            let event_state = self.states[i].event_state();
+            if event_state.intersects(State::UNKNOWN | State::IGNORE) {
+                let rs = self.states.remove(i);
+                events.push(ReaderEvent::ReaderRemoved(rs.name().to_owned()));
+                continue;
+            }
+
+            let rs = &mut self.states[i];
+            let current_state = State::from_bits_truncate(rs.inner.dwCurrentState);
+            let current_count = ((rs.inner.dwCurrentState & 0xFFFF_0000) >> 16) as u32;
+            let was_present = current_state.contains(State::PRESENT);
+            let is_present = event_state.contains(State::PRESENT);
+            // A changed event count with a card present before and after
+            // means that the card was swapped.
+            let swapped = was_present && is_present && current_count != rs.event_count();
+            if was_present && (!is_present || swapped) {
+                events.push(ReaderEvent::CardRemoved(rs.name().to_owned()));
+            }
+            if is_present && (!was_present || swapped) {
+                events.push(ReaderEvent::CardInserted(rs.name().to_owned(), rs.atr().to_vec()));
+            }
+            rs.sync_current_state();
+            i += 1;
+        }
+
+        if self.states[0].sync_current_state_changed() {
+            let names = context.list_readers_owned()?;
+
+            let mut i = 1;
+            while i < self.states.len() {
+                if names.iter().any(|name| self.states[i].has_name(name)) {
+                    i += 1;
+                } else {
+                    let rs = self.states.remove(i);
+                    events.push(ReaderEvent::ReaderRemoved(rs.name().to_owned()));
+                }
+            }
+
+            for name in names {
+                if !self.states.iter().any(|rs| rs.has_name(&name)) {
+                    events.push(ReaderEvent::ReaderAdded(name.clone()));
+                    self.states.push(ReaderState::new(name, State::UNAWARE));
+                }
+            }
+        }
+
+        Ok(events)
+    }
+}
+
+impl Default for ReaderSet {
+    fn default() -> ReaderSet {
+        ReaderSet::new()
+    }
+}
+
+/// The availability of the card in a card reader for connection.
+///
+/// Returned by `Context::readers_availability`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Availability {
+    /// A card is present and no application is connected to it.
+    Free,
+    /// A card is present and in use by other applications, but can be
+    /// connected to in `ShareMode::Shared`.
+    Shared,
+    /// A card is present and connected to by another application in
+    /// `ShareMode::Exclusive`.
+    Exclusive,
+    /// No card is present.
+    Empty,
+}
+
+/// A snapshot of the state of a card reader.
+///
+/// Snapshots are returned by `Context::enumerate`.
+#[derive(Debug, Clone)]
+pub struct ReaderInfoSnapshot {
+    name: CString,
+    state: State,
+    atr: Option<Vec<u8>>,
+}
+
+impl ReaderInfoSnapshot {
+    /// The name of the card reader.
+    pub fn name(&self) -> &CStr {
+        &self.name
+    }
+
+    /// The state of the card reader at the time of the snapshot.
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// The ATR (Answer To Reset) of the card inserted to the reader, or
+    /// `None` if there is no card.
+    pub fn atr(&self) -> Option<&[u8]> {
+        self.atr.as_ref().map(|atr| &atr[..])
+    }
+}
+
+/// The PIN pad capabilities of a card reader.
+///
+/// Capabilities are returned by `Card::pinpad_capabilities`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PinpadCaps {
+    verify_pin_direct: bool,
+    modify_pin_direct: bool,
+    display: bool,
+    auth_input_device: bool,
+    max_pin_size: Option<u8>,
+    max_input: Option<u32>,
+}
+
+impl PinpadCaps {
+    /// Whether the reader can verify a PIN entered on its PIN pad
+    /// (`FEATURE_VERIFY_PIN_DIRECT`).
+    pub fn verify_pin_direct(&self) -> bool {
+        self.verify_pin_direct
+    }
+
+    /// Whether the reader can modify a PIN entered on its PIN pad
+    /// (`FEATURE_MODIFY_PIN_DIRECT`).
+    pub fn modify_pin_direct(&self) -> bool {
+        self.modify_pin_direct
+    }
+
+    /// Whether the reader has a display.
+    pub fn display(&self) -> bool {
+        self.display
+    }
+
+    /// Whether the reader reports a user authentication input device,
+    /// through `Attribute::UserAuthInputDevice` or
+    /// `Attribute::UserToCardAuthDevice`.
+    pub fn auth_input_device(&self) -> bool {
+        self.auth_input_device
+    }
+
+    /// The maximum PIN size accepted by the PIN pad, if reported by the
+    /// reader (`bMaxPINSize`).
+    pub fn max_pin_size(&self) -> Option<u8> {
+        self.max_pin_size
+    }
+
+    /// The maximum input size of the reader, if reported
+    /// (`Attribute::Maxinput`).
+    pub fn max_input(&self) -> Option<u32> {
+        self.max_input
+    }
+
+    /// Whether PIN entry on the reader is supported, i.e. whether a PIN
+    /// should be entered on the reader rather than in software.
+    pub fn pin_entry(&self) -> bool {
+        self.verify_pin_direct || self.modify_pin_direct
+    }
+}
+
+/// The card capabilities of a card, as indicated in the historical bytes
+/// of its ATR.
+///
+/// Capabilities are returned by `Card::card_capabilities`. See
+/// [ISO 7816 Part 4][1] section 8.1.1.2.7 for the meaning of the bytes.
+///
+/// [1]: http://www.cardwerk.com/smartcards/smartcard_standard_ISO7816-4_8_historical_characters.aspx
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CardCapabilities {
+    selection_methods: u8,
+    data_coding: Option<u8>,
+    flags: Option<u8>,
+}
+
+impl CardCapabilities {
+    /// The selection methods byte (first software function table).
+    pub fn selection_methods(&self) -> u8 {
+        self.selection_methods
+    }
+
+    /// Whether DF selection by full DF name is supported.
+    pub fn select_by_full_name(&self) -> bool {
+        self.selection_methods & 0x80 != 0
+    }
+
+    /// Whether DF selection by partial DF name is supported.
+    pub fn select_by_partial_name(&self) -> bool {
+        self.selection_methods & 0x40 != 0
+    }
+
+    /// Whether DF selection by path is supported.
+    pub fn select_by_path(&self) -> bool {
+        self.selection_methods & 0x20 != 0
+    }
+
+    /// Whether DF selection by file identifier is supported.
+    pub fn select_by_file_id(&self) -> bool {
+        self.selection_methods & 0x10 != 0
+    }
+
+    /// The data coding byte (second software function table), if present.
+    pub fn data_coding(&self) -> Option<u8> {
+        self.data_coding
+    }
+
+    /// Whether command chaining is supported.
+    pub fn command_chaining(&self) -> bool {
+        match self.flags {
+            Some(flags) => flags & 0x80 != 0,
+            None => false,
+        }
+    }
+
+    /// Whether extended Lc and Le fields (extended APDUs) are supported.
+    pub fn extended_length(&self) -> bool {
+        match self.flags {
+            Some(flags) => flags & 0x40 != 0,
+            None => false,
+        }
+    }
+
+    /// The maximum number of logical channels, if indicated.
+    pub fn max_logical_channels(&self) -> Option<u8> {
+        // Encoded as the number minus one; 7 means 8 or more.
+        self.flags.map(|flags| (flags & 0x07) + 1)
+    }
+}
+
+/// A registry of known ATRs (Answer To Reset), used to identify cards.
+///
+/// Each entry consists of an ATR pattern, a mask and a label. An ATR
+/// matches an entry if it has the same length as the pattern, and is
+/// equal to the pattern on all the bits set in the mask. This is the same
+/// matching as done by `SCardLocateCardsByATR`.
+///
+/// See `Card::identify`.
+#[derive(Debug, Clone, Default)]
+pub struct AtrRegistry {
+    entries: Vec<(Vec<u8>, Vec<u8>, String)>,
+}
+
+impl AtrRegistry {
+    /// Create an empty registry.
+    pub fn new() -> AtrRegistry {
+        AtrRegistry {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Add an entry to the registry.
+    ///
+    /// Entries are matched in the order they were added.
+    ///
+    /// Panics if `pattern` and `mask` do not have the same length.
+    pub fn add<S>(
+        &mut self,
+        pattern: &[u8],
+        mask: &[u8],
+        label: S,
+    )
+        where S: Into<String> {
+        assert_eq!(pattern.len(), mask.len());
+        self.entries.push((pattern.to_vec(), mask.to_vec(), label.into()));
+    }
+
+    /// Find the label of the first entry matching `atr`, if any.
+    pub fn match_card(
+        &self,
+        atr: &[u8],
+    ) -> Option<&str> {
+        self.entries.iter()
+            .find(|entry| atr_matches(atr, &entry.0, &entry.1))
+            .map(|entry| &entry.2[..])
+    }
+}
+
+// Check that the length of an APDU command matches the Lc and Le fields
+// of its body, according to the cases of ISO 7816-3 section 12.1.
+fn is_valid_apdu_command(command: &[u8]) -> bool {
+    if command.len() < APDU_HEADER_SIZE {
+        return false;
+    }
+    let body = &command[APDU_HEADER_SIZE..];
+    match body.len() {
+        // Case 1, or case 2 short.
+        0 | 1 => true,
+        _ if body[0] != 0 => {
+            // Case 3 or 4 short.
+            let lc = body[0] as usize;
+            body.len() == 1 + lc || body.len() == 1 + lc + 1
+        },
+        // Case 2 extended.
+        3 => true,
+        len if len > 3 => {
+            // Case 3 or 4 extended.
+            let lc = ((body[1] as usize) << 8) | body[2] as usize;
+            lc != 0 && (len == 3 + lc || len == 3 + lc + 2)
+        },
+        _ => false,
+    }
+}
+
+// Get the historical bytes of an ATR, or `None` if the ATR is malformed.
+fn atr_historical_bytes(atr: &[u8]) -> Option<&[u8]> {
+    // Skip TS.
+    let t0 = match atr.get(1) {
+        Some(&t0) => t0,
+        None => return None,
+    };
+    let num_historical = (t0 & 0x0F) as usize;
+
+    // Skip the interface bytes, as indicated by T0 and each TDi.
+    let mut pos = 2;
+    let mut indicator = t0;
+    loop {
+        let present = (indicator >> 4).count_ones() as usize;
+        let has_td = indicator & 0x80 != 0;
+        pos += present;
+        if !has_td {
+            break;
+        }
+        indicator = match atr.get(pos - 1) {
+            Some(&td) => td,
+            None => return None,
+        };
+    }
+
+    atr.get(pos..pos + num_historical)
+}
+
+// Get the card capabilities from the historical bytes of an ATR.
+fn card_capabilities_from_historical(historical: &[u8]) -> Result<CardCapabilities, Error> {
+    // Only the category indicators 00 and 80 are followed by
+    // compact-TLV data objects. With 00, the last 3 bytes are a
+    // status indicator, which is not a data object.
+    let objects = match historical.split_first() {
+        Some((&0x80, objects)) => objects,
+        Some((&0x00, objects)) if objects.len() >= 3 => &objects[..objects.len() - 3],
+        _ => return Err(Error::UnsupportedFeature),
+    };
+
+    let mut pos = 0;
+    while pos < objects.len() {
+        let tag = objects[pos] >> 4;
+        let len = (objects[pos] & 0x0F) as usize;
+        let value = match objects.get(pos + 1..pos + 1 + len) {
+            Some(value) => value,
+            None => return Err(Error::InvalidAtr),
+        };
+        if tag == 0x7 && !value.is_empty() {
+            return Ok(CardCapabilities {
+                selection_methods: value[0],
+                data_coding: value.get(1).cloned(),
+                flags: value.get(2).cloned(),
+            });
+        }
+        pos += 1 + len;
+    }
+
+    Err(Error::UnsupportedFeature)
+}
+
+// Convert a byte between the direct and inverse conventions, by reversing
+// the bits and complementing. This is its own inverse.
+fn convert_inverse_convention(b: u8) -> u8 {
+    let reversed = (0..8).fold(0, |acc, i| acc | (((b >> i) & 1) << (7 - i)));
+    !reversed
+}
+
+// Check whether an ATR matches a pattern on the bits set in the mask.
+fn atr_matches(atr: &[u8], pattern: &[u8], mask: &[u8]) -> bool {
+    atr.len() == pattern.len()
+        && mask.len() == pattern.len()
+        && atr.iter().zip(pattern).zip(mask).all(|((&a, &p), &m)| a & m == p & m)
+}
+
+// Bits of `Attribute::AsyncProtocolTypes`, from the PC/SC specification
+// part 3.
+const ASYNC_PROTOCOL_T0: u32 = 0x0000_0001;
+const ASYNC_PROTOCOL_T1: u32 = 0x0000_0002;
+
+// Card ejection mechanism bit of `Attribute::Characteristics`, from the
+// PC/SC specification part 3.
+const CHARACTERISTICS_EJECTION: u32 = 0x0000_0002;
+
+// Control code and tags from the PC/SC specification part 10.
+const CM_IOCTL_GET_FEATURE_REQUEST: DWORD = 3400;
+const FEATURE_VERIFY_PIN_DIRECT: u32 = 0x06;
+const FEATURE_MODIFY_PIN_DIRECT: u32 = 0x07;
+const FEATURE_IFD_DISPLAY_PROPERTIES: u32 = 0x11;
+const FEATURE_GET_TLV_PROPERTIES: u32 = 0x12;
+const PCSCV2_PART10_PROPERTY_W_LCD_LAYOUT: u32 = 0x01;
+const PCSCV2_PART10_PROPERTY_B_MAX_PIN_SIZE: u32 = 0x07;
+
+impl Drop for ReaderState {
+    fn drop(&mut self) {
+        // Reclaim the name and drop it immediately.
+        unsafe { CString::from_raw(self.inner.szReader as *mut c_char) };
+    }
+}
+
+impl Card {
+    /// Get a `Canceler` for the context of the card.
+    ///
+    /// See `Context::canceler`.
+    pub fn canceler(
+        &self,
+    ) -> Canceler {
+        self._context.canceler()
+    }
+
+    /// Start a new exclusive transaction with the card.
+    ///
+    /// Operations on the card for the duration of the transaction
+    /// can only be performed through the returned `Transaction`.
+    ///
+    /// This function wraps `SCardBeginTransaction` ([pcsclite][1],
+    /// [MSDN][2]).
+    ///
+    /// [1]: https://pcsclite.apdu.fr/api/group__API.html#gaddb835dce01a0da1d6ca02d33ee7d861
+    /// [2]: https://msdn.microsoft.com/en-us/library/aa379469.aspx
+    pub fn transaction(
+        &mut self,
+    ) -> Result<Transaction, Error> {
+        unsafe {
+            try_pcsc!(ffi::SCardBeginTransaction(
+                self.handle,
+            ));
+
+            Ok(Transaction {
+                card: self,
+            })
+        }
+    }
+
+    /// Select the EMV payment system environment of the card.
+    ///
+    /// The payment system directory `1PAY.SYS.DDF01` (PSE) is selected,
+    /// or `2PAY.SYS.DDF01` (PPSE) if `contactless` is true.
+    ///
+    /// Returns the FCI template of the directory. The status words `61 XX`
+    /// and `6C XX` are handled to fetch the FCI, as in `read_binary`. If
+    /// the card returns another unsuccessful status word (e.g. `6A 82` if
+    /// it has no such directory), `HelperError::Apdu` is returned.
+    pub fn select_pse(
+        &self,
+        contactless: bool,
+    ) -> Result<Vec<u8>, HelperError> {
+        let name: &[u8] = if contactless {
+            b"2PAY.SYS.DDF01"
+        } else {
+            b"1PAY.SYS.DDF01"
+        };
+        self.select_by_name(name)
+    }
+
+    /// Read a transparent file with `READ BINARY` commands.
+    ///
+    /// If `sfi` is given, the file with that short file identifier is
+    /// read (and becomes the current file); otherwise, the current file is
+    /// read.
+    ///
+    /// The file is read in chunks of up to 256 bytes, advancing the offset,
+    /// until `length` bytes are read or the end of the file is reached
+    /// (status word `62 82`, `6B 00`, or an empty response). The responses
+    /// `6C XX` (wrong length) and `61 XX` (more data available) are
+    /// handled by resending the command and with `GET RESPONSE`,
+    /// respectively. Any other unsuccessful status word is returned as
+    /// `HelperError::Apdu`.
+    ///
+    /// Offsets above `7FFF` cannot be encoded; if `length` requires
+    /// reading beyond it, `Error::InvalidParameter` is returned.
+    pub fn read_binary(
+        &self,
+        sfi: Option<u8>,
+        length: usize,
+    ) -> Result<Vec<u8>, HelperError> {
+        let mut data = Vec::with_capacity(length);
+        while data.len() < length {
+            let offset = data.len();
+            let (p1, p2) = match sfi {
+                Some(sfi) if offset == 0 => (0x80 | (sfi & 0x1F), 0),
+                _ if offset <= 0x7FFF => ((offset >> 8) as u8, offset as u8),
+                _ => return Err(Error::InvalidParameter.into()),
+            };
+            // An Le of 0 means 256.
+            let le = std::cmp::min(length - offset, 256) as u8;
+
+            let (chunk, sw) = self.transmit_short_le(&[0x00, 0xB0, p1, p2, le])?;
+            match sw {
+                0x9000 if !chunk.is_empty() => data.extend_from_slice(&chunk),
+                0x9000 | 0x6B00 => break,
+                0x6282 => {
+                    data.extend_from_slice(&chunk);
+                    break;
+                },
+                sw => return Err(ApduError::new(iso7816::StatusWord::new(sw)).into()),
+            }
+        }
+        data.truncate(length);
+        Ok(data)
+    }
+
+    /// Write to a transparent file with `UPDATE BINARY` commands.
+    ///
+    /// If `sfi` is given, the file with that short file identifier is
+    /// written (and becomes the current file); otherwise, the current file
+    /// is written. `data` is written starting at `offset` in the file.
+    ///
+    /// The data is written in chunks of up to 255 bytes, advancing the
+    /// offset. Returns the status word of the last command; if a command
+    /// fails with an unsuccessful status word, the remaining chunks are not
+    /// written, and that status word is returned.
+    ///
+    /// With `sfi`, the initial `offset` must be at most `FF`, and in all
+    /// cases offsets above `7FFF` cannot be encoded; otherwise,
+    /// `Error::InvalidParameter` is returned before writing anything. If
+    /// `data` is empty, no command is sent.
+    pub fn update_binary(
+        &self,
+        sfi: Option<u8>,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<u16, Error> {
+        // Check the offsets up front, to not write the file partially.
+        let last_offset = offset + data.len().saturating_sub(1) / 255 * 255;
+        if (sfi.is_some() && offset > 0xFF) || last_offset > 0x7FFF {
+            return Err(Error::InvalidParameter);
+        }
+
+        let mut sw = 0x9000;
+        for (i, chunk) in data.chunks(255).enumerate() {
+            let chunk_offset = offset + i * 255;
+            let (p1, p2) = match sfi {
+                Some(sfi) if i == 0 => (0x80 | (sfi & 0x1F), chunk_offset as u8),
+                _ => ((chunk_offset >> 8) as u8, chunk_offset as u8),
+            };
+
+            let mut command = Vec::with_capacity(APDU_HEADER_SIZE + 1 + chunk.len());
+            command.extend_from_slice(&[0x00, 0xD6, p1, p2, chunk.len() as u8]);
+            command.extend_from_slice(chunk);
+            sw = self.transmit_apdu_owned(&command)?.1;
+            if sw != 0x9000 {
+                break;
+            }
+        }
+        Ok(sw)
+    }
+
+    // Transmit a short APDU command ending with Le, handling the status
+    // words of ISO 7816-4 which ask to fetch the response differently:
+    // `6C XX` (wrong Le) by resending the command with Le set to XX, and
+    // `61 XX` (more data available, e.g. with T=0) with GET RESPONSE.
+    fn transmit_short_le(
+        &self,
+        command: &[u8],
+    ) -> Result<(Vec<u8>, u16), Error> {
+        let (mut data, mut sw) = self.transmit_apdu_owned(command)?;
+        if sw & 0xFF00 == 0x6C00 {
+            let mut command = command.to_vec();
+            if let Some(le) = command.last_mut() {
+                *le = sw as u8;
+            }
+            let response = self.transmit_apdu_owned(&command)?;
+            data = response.0;
+            sw = response.1;
+        }
+
+        while sw & 0xFF00 == 0x6100 {
+            let (more, more_sw) = self.transmit_apdu_owned(&[0x00, 0xC0, 0x00, 0x00, sw as u8])?;
+            data.extend_from_slice(&more);
+            sw = more_sw;
+        }
+        Ok((data, sw))
+    }
+
+    // Send a SELECT command by DF name (ISO 7816-4), requesting the FCI.
+    fn select_by_name(
+        &self,
+        name: &[u8],
+    ) -> Result<Vec<u8>, HelperError> {
+        assert!(name.len() <= 16);
+        let mut command = vec![0x00, 0xA4, 0x04, 0x00, name.len() as u8];
+        command.extend_from_slice(name);
+        command.push(0x00);
+        let (fci, sw) = self.transmit_short_le(&command)?;
+        let sw = iso7816::StatusWord::new(sw);
+        if !sw.is_success() {
+            return Err(ApduError::new(sw).into());
+        }
+        Ok(fci)
+    }
+
+    /// Transmit a sequence of APDU commands to the card, within a single
+    /// transaction.
+    ///
+    /// A transaction is started, and each of `commands` is transmitted in
+    /// order, as with `transmit_apdu_owned`. The transaction is then ended
+    /// with `Disposition::LeaveCard`.
+    ///
+    /// Returns the response data and status word for each command. The
+    /// status words are not checked; if transmitting a command fails, the
+    /// remaining commands are not transmitted, and the error is returned.
+    pub fn transact_sequence(
+        &mut self,
+        commands: &[&[u8]],
+    ) -> Result<Vec<(Vec<u8>, u16)>, Error> {
+        let tx = self.transaction()?;
+        let mut responses = Vec::with_capacity(commands.len());
+        for command in commands {
+            responses.push(tx.transmit_apdu_owned(command)?);
+        }
+        tx.end(Disposition::LeaveCard).map_err(|(_, err)| err)?;
+        Ok(responses)
+    }
+
+    /// Check whether the card reader supports transactions.
+    ///
+    /// Some readers (e.g. some virtual readers) fail `transaction` with
+    /// `Error::UnsupportedFeature`. This function probes for this by
+    /// starting a transaction and immediately ending it, leaving the card
+    /// as is. The result is cached, so the probe is performed at most once
+    /// per `Card`.
+    ///
+    /// If the probe fails with another error, the reader is assumed to
+    /// support transactions, and the result is not cached.
+    ///
+    /// ## Note
+    ///
+    /// If another connection holds a transaction on the card, the probe
+    /// blocks until that transaction ends.
+    pub fn supports_transactions(
+        &self,
+    ) -> bool {
+        match self.transactions_support.load(Ordering::Relaxed) {
+            TRANSACTIONS_SUPPORTED => return true,
+            TRANSACTIONS_UNSUPPORTED => return false,
+            _ => {},
+        }
+
+        unsafe {
+            let err = ffi::SCardBeginTransaction(
+                self.handle,
+            );
+            if err == ffi::SCARD_S_SUCCESS {
+                // Error is ignored here; the probe already succeeded.
+                ffi::SCardEndTransaction(
+                    self.handle,
+                    Disposition::LeaveCard.into_raw(),
+                );
+                self.transactions_support.store(TRANSACTIONS_SUPPORTED, Ordering::Relaxed);
+                return true;
+            }
+            if err == Error::UnsupportedFeature.into_raw() {
+                self.transactions_support.store(TRANSACTIONS_UNSUPPORTED, Ordering::Relaxed);
+                return false;
+            }
+
+            true
+        }
+    }
+
+    /// Start a new exclusive transaction with the card, giving up after a
+    /// timeout.
+    ///
+    /// This function is like `transaction`, but if the transaction could
+    /// not be started within `timeout` (e.g. because another connection
+    /// holds a transaction), the attempt is canceled and `Error::Timeout`
+    /// is returned.
+    ///
+    /// ## Note
+    ///
+    /// The attempt is canceled using `Context::cancel` from a watchdog
+    /// thread, which also cancels any other ongoing blocking operation in
+    /// the card's context. Whether `SCardBeginTransaction` can be canceled
+    /// depends on the PC/SC implementation; if it cannot, this function
+    /// behaves like `transaction`.
+    pub fn transaction_timeout(
+        &mut self,
+        timeout: std::time::Duration,
+    ) -> Result<Transaction, Error> {
+        let canceler = self.canceler();
+        let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+        let watchdog = std::thread::spawn(move || {
+            if let Err(std::sync::mpsc::RecvTimeoutError::Timeout) = done_rx.recv_timeout(timeout) {
+                let _ = canceler.cancel();
+                true
+            } else {
+                false
+            }
+        });
+
+        unsafe {
+            let err = ffi::SCardBeginTransaction(
+                self.handle,
+            );
+            drop(done_tx);
+            let timed_out = watchdog.join().unwrap_or(false);
+
+            if err != ffi::SCARD_S_SUCCESS {
+                let err = Error::from_raw(err);
+                if timed_out && err == Error::Cancelled {
+                    return Err(Error::Timeout);
+                }
+                return Err(err);
+            }
+
+            Ok(Transaction {
+                card: self,
+            })
+        }
+    }
+
+    /// Reconnect to the card.
+    ///
+    /// This function wraps `SCardReconnect` ([pcsclite][1], [MSDN][2]).
+    ///
+    /// [1]: https://pcsclite.apdu.fr/api/group__API.html#gad5d4393ca8c470112ad9468c44ed8940
+    /// [2]: https://msdn.microsoft.com/en-us/library/aa379797.aspx
+    pub fn reconnect(
+        &mut self,
+        share_mode: ShareMode,
+        preferred_protocols: Protocols,
+        initialization: Disposition,
+    ) -> Result<(), Error> {
+        unsafe {
+            let mut raw_active_protocol: DWORD = DUMMY_DWORD;
+
+            try_pcsc!(ffi::SCardReconnect(
+                self.handle,
+                share_mode.into_raw(),
+                preferred_protocols.bits(),
+                initialization.into_raw(),
+                &mut raw_active_protocol,
+            ));
+
+            self.active_protocol.store(raw_active_protocol as usize, Ordering::Relaxed);
+            self.share_mode = share_mode;
+            self.preferred_protocols = preferred_protocols;
+
+            Ok(())
+        }
+    }
+
+    /// Perform a warm reset of the card.
+    ///
+    /// The card is reset without removing its power, using `reconnect`
+    /// with `Disposition::ResetCard` and the share mode and protocols of
+    /// the current connection.
+    ///
+    /// Returns the ATR (Answer To Reset) of the card following the reset.
+    pub fn warm_reset(
+        &mut self,
+    ) -> Result<Vec<u8>, Error> {
+        let (share_mode, preferred_protocols) = (self.share_mode, self.preferred_protocols);
+        self.reconnect(share_mode, preferred_protocols, Disposition::ResetCard)?;
+        self.get_atr_vec()
+    }
+
+    /// Power cycle the card, and check that it is present afterwards.
+    ///
+    /// The card is powered down using `reconnect` with
+    /// `Disposition::UnpowerCard`, and then reset using `reconnect` with
+    /// `Disposition::ResetCard`, keeping the share mode and protocols of
+    /// the current connection. If the card is not present afterwards,
+    /// `Error::RemovedCard` is returned.
+    ///
+    /// Returns the ATR (Answer To Reset) of the card following the power
+    /// cycle.
+    pub fn power_cycle(
+        &mut self,
+    ) -> Result<Vec<u8>, Error> {
+        let (share_mode, preferred_protocols) = (self.share_mode, self.preferred_protocols);
+        self.reconnect(share_mode, preferred_protocols, Disposition::UnpowerCard)?;
+        self.reconnect(share_mode, preferred_protocols, Disposition::ResetCard)?;
+        let (status, atr) = self.get_status_atr()?;
+        if !status.contains(Status::PRESENT) {
+            return Err(Error::RemovedCard);
+        }
+        Ok(atr)
+    }
+
+    /// Perform a cold reset of the card.
+    ///
+    /// The card is powered down and up again, using `reconnect` with
+    /// `Disposition::UnpowerCard` and the share mode and protocols of
+    /// the current connection.
+    ///
+    /// Returns the ATR (Answer To Reset) of the card following the reset.
+    pub fn cold_reset(
+        &mut self,
+    ) -> Result<Vec<u8>, Error> {
+        let (share_mode, preferred_protocols) = (self.share_mode, self.preferred_protocols);
+        self.reconnect(share_mode, preferred_protocols, Disposition::UnpowerCard)?;
+        self.get_atr_vec()
+    }
+
+    /// Disconnect from the card.
+    ///
+    /// In case of error, ownership of the card is returned to the caller.
+    ///
+    /// This function wraps `SCardDisconnect` ([pcsclite][1], [MSDN][2]).
+    ///
+    /// [1]: https://pcsclite.apdu.fr/api/group__API.html#ga4be198045c73ec0deb79e66c0ca1738a
+    /// [2]: https://msdn.microsoft.com/en-us/library/aa379475.aspx
+    ///
+    /// ## Note
+    ///
+    /// `Card` implements `Drop` which automatically disconnects the card
+    /// using `Disposition::ResetCard`; you only need to call this
+    /// function if you want to handle errors or use a different
+    /// disposition method.
+    pub fn disconnect(
+        mut self,
+        disposition: Disposition,
+    ) -> Result<(), (Card, Error)> {
+        unsafe {
+            let err = ffi::SCardDisconnect(
+                self.handle,
+                disposition.into_raw(),
+            );
+            if err != ffi::SCARD_S_SUCCESS {
+                return Err((self, Error::from_raw(err)));
+            }
+
+            // Skip the drop, we did it "manually".
+            std::ptr::drop_in_place(&mut self._context);
+            forget(self);
+
+            Ok(())
+        }
+    }
+
+    /// Abandon the connection without disconnecting, returning the raw
+    /// `SCARDHANDLE` handle.
+    ///
+    /// After this call, the card is never disconnected by this library.
+    /// Since a card handle is only valid as long as its context, the
+    /// card's context is abandoned as well (see `Context::leak`). The
+    /// caller becomes responsible for both handles.
+    pub fn leak(
+        self,
+    ) -> ffi::SCARDHANDLE {
+        let handle = self.handle;
+        forget(self);
+        handle
+    }
+
+    /// Disconnect from the card, trying several dispositions until one
+    /// succeeds.
+    ///
+    /// `disconnect` returns the card on failure, which is awkward when
+    /// the disposition cannot be applied, e.g. because the card was
+    /// removed. This function instead tries `Disposition::LeaveCard`,
+    /// `Disposition::ResetCard` and `Disposition::UnpowerCard`, in order.
+    ///
+    /// If all of them fail, the card handle is abandoned (as with `leak`,
+    /// except that the context is not abandoned), and the last error is
+    /// returned. Either way, the `Card` is consumed.
+    pub fn force_disconnect(
+        self,
+    ) -> Result<(), Error> {
+        let dispositions = [
+            Disposition::LeaveCard,
+            Disposition::ResetCard,
+            Disposition::UnpowerCard,
+        ];
+
+        let mut card = self;
+        let mut last_err = Error::UnknownError;
+        for &disposition in &dispositions {
+            match card.disconnect(disposition) {
+                Ok(()) => return Ok(()),
+                Err((returned_card, err)) => {
+                    card = returned_card;
+                    last_err = err;
+                }
+            }
+        }
+
+        unsafe {
+            // Skip the drop, which would only fail again.
+            std::ptr::drop_in_place(&mut card._context);
+            forget(card);
+        }
+
+        Err(last_err)
+    }
+
+    /// Disconnect from the card, leaving it as is.
+    ///
+    /// This is `disconnect` with `Disposition::LeaveCard`.
+    pub fn disconnect_leave(
+        self,
+    ) -> Result<(), (Card, Error)> {
+        self.disconnect(Disposition::LeaveCard)
+    }
+
+    /// Disconnect from the card, resetting it.
+    ///
+    /// This is `disconnect` with `Disposition::ResetCard`.
+    pub fn disconnect_reset(
+        self,
+    ) -> Result<(), (Card, Error)> {
+        self.disconnect(Disposition::ResetCard)
+    }
+
+    /// Disconnect from the card, powering it down.
+    ///
+    /// This is `disconnect` with `Disposition::UnpowerCard`.
+    pub fn disconnect_unpower(
+        self,
+    ) -> Result<(), (Card, Error)> {
+        self.disconnect(Disposition::UnpowerCard)
+    }
+
+    /// Disconnect from the card, ejecting it.
+    ///
+    /// This is `disconnect` with `Disposition::EjectCard`. Most readers
+    /// do not support ejecting the card.
+    pub fn disconnect_eject(
+        self,
+    ) -> Result<(), (Card, Error)> {
+        self.disconnect(Disposition::EjectCard)
+    }
+
+    /// Eject the card and disconnect from it, checking that the reader
+    /// can eject cards.
+    ///
+    /// If the reader reports its mechanical characteristics
+    /// (`Attribute::Characteristics`) and they do not include a card
+    /// ejection mechanism, `Error::UnsupportedFeature` is returned and
+    /// the card is not disconnected. If the reader does not report them,
+    /// this is the same as `disconnect_eject`.
+    pub fn eject(
+        self,
+    ) -> Result<(), (Card, Error)> {
+        if let Ok(characteristics) = self.get_attribute_u32(Attribute::Characteristics) {
+            if characteristics & CHARACTERISTICS_EJECTION == 0 {
+                return Err((self, Error::UnsupportedFeature));
+            }
+        }
+        self.disconnect(Disposition::EjectCard)
+    }
+
+    /// Get the active protocol of the connection, or `None` for direct
+    /// connections.
+    ///
+    /// The active protocol is set by `connect` and `reconnect`. If it is
+    /// changed externally, for example after the card is reset by another
+    /// application, it is updated the next time `status` is called.
+    pub fn protocol(
+        &self,
+    ) -> Option<Protocol> {
+        Protocol::from_raw_opt(self.active_protocol.load(Ordering::Relaxed) as DWORD)
+    }
+
+    /// Get current info on the card.
+    ///
+    /// The active protocol returned by `SCardStatus` is also recorded, so
+    /// that `protocol` stays accurate.
+    ///
+    /// Direct connections have no active protocol; for them,
+    /// `Error::UnsupportedFeature` is returned.
+    ///
+    /// This function wraps `SCardStatus` ([pcsclite][1], [MSDN][2]).
+    ///
+    /// [1]: https://pcsclite.apdu.fr/api/group__API.html#gae49c3c894ad7ac12a5b896bde70d0382
+    /// [2]: https://msdn.microsoft.com/en-us/library/aa379803.aspx
+    // TODO: Missing return values: reader names and ATR.
+    pub fn status(
+        &self,
+    ) -> Result<(Status, Protocol), Error> {
+        unsafe {
+            let mut raw_status: DWORD = DUMMY_DWORD;
+            let mut raw_protocol: DWORD = DUMMY_DWORD;
+
+            try_pcsc!(ffi::SCardStatus(
+                self.handle,
+                null_mut(),
+                null_mut(),
+                &mut raw_status,
+                &mut raw_protocol,
+                null_mut(),
+                null_mut(),
+            ));
+
+            self.active_protocol.store(raw_protocol as usize, Ordering::Relaxed);
+
+            let status = Status::from_bits_truncate(raw_status);
+            let protocol = match Protocol::from_raw_opt(raw_protocol) {
+                Some(protocol) => protocol,
+                None => return Err(Error::UnsupportedFeature),
+            };
+
+            Ok((status, protocol))
+        }
+    }
+
+    // Get the ATR of the card, using `SCardStatus`.
+    fn get_atr_vec(
+        &self,
+    ) -> Result<Vec<u8>, Error> {
+        Ok(self.get_status_atr()?.1)
+    }
+
+    // Get the status and ATR of the card, using `SCardStatus`. Unlike
+    // `status`, this also works with direct connections.
+    fn get_status_atr(
+        &self,
+    ) -> Result<(Status, Vec<u8>), Error> {
+        unsafe {
+            let mut raw_status: DWORD = DUMMY_DWORD;
+            let mut raw_protocol: DWORD = DUMMY_DWORD;
+            let mut atr = vec![0; ffi::ATR_BUFFER_SIZE];
+            let mut atr_len = atr.len() as DWORD;
+
+            try_pcsc!(ffi::SCardStatus(
+                self.handle,
+                null_mut(),
+                null_mut(),
+                &mut raw_status,
+                &mut raw_protocol,
+                atr.as_mut_ptr(),
+                &mut atr_len,
+            ));
+
+            self.active_protocol.store(raw_protocol as usize, Ordering::Relaxed);
+
+            atr.truncate(atr_len as usize);
+            Ok((Status::from_bits_truncate(raw_status), atr))
+        }
+    }
+
+    /// Get the convention of the ATR (Answer To Reset) of the card.
+    ///
+    /// The reader handles the convention transparently when exchanging
+    /// APDUs; this is mostly useful for analyzing the ATR.
+    ///
+    /// If the initial character of the ATR is invalid,
+    /// `Error::InvalidAtr` is returned.
+    pub fn atr_convention(
+        &self,
+    ) -> Result<Convention, Error> {
+        match self.get_atr_vec()?.first() {
+            Some(&0x3B) => Ok(Convention::Direct),
+            Some(&0x3F) => Ok(Convention::Inverse),
+            _ => Err(Error::InvalidAtr),
+        }
+    }
+
+    /// Identify the card by looking up its ATR (Answer To Reset) in a
+    /// registry.
+    ///
+    /// Returns the label of the first matching entry, or `None` if the
+    /// card is not known to the registry.
+    pub fn identify(
+        &self,
+        registry: &AtrRegistry,
+    ) -> Result<Option<String>, Error> {
+        let atr = self.get_atr_vec()?;
+        Ok(registry.match_card(&atr).map(|label| label.to_owned()))
+    }
+
+    /// Get the card capabilities indicated in the historical bytes of the
+    /// ATR (Answer To Reset) of the card.
+    ///
+    /// The capabilities are the compact-TLV data object with tag `7` in
+    /// the historical bytes.
+    ///
+    /// If the ATR is malformed, `Error::InvalidAtr` is returned. If the
+    /// historical bytes do not contain the card capabilities,
+    /// `Error::UnsupportedFeature` is returned.
+    pub fn card_capabilities(
+        &self,
+    ) -> Result<CardCapabilities, Error> {
+        let atr = self.get_atr_vec()?;
+        let historical = match atr_historical_bytes(&atr) {
+            Some(historical) => historical,
+            None => return Err(Error::InvalidAtr),
+        };
+
+        card_capabilities_from_historical(historical)
+    }
+
+    /// Get an attribute of the card or card reader.
+    ///
+    /// `buffer` is a buffer that should be large enough for the attribute
+    /// data. The function `get_attribute_len` can be used to find the
+    /// exact required length.
+    ///
+    /// Returns a slice into `buffer` containing the attribute data.
+    ///
+    /// If the buffer is not large enough, `Error::InsufficientBuffer` is
+    /// returned.
+    ///
+    /// This function wraps `SCardGetAttrib` ([pcsclite][1], [MSDN][2]).
+    ///
+    /// [1]: https://pcsclite.apdu.fr/api/group__API.html#gaacfec51917255b7a25b94c5104961602
+    /// [2]: https://msdn.microsoft.com/en-us/library/aa379559.aspx
+    pub fn get_attribute<'buf>(
+        &self,
+        attribute: Attribute,
+        buffer: &'buf mut [u8],
+    ) -> Result<&'buf [u8], Error> {
+        unsafe {
+            assert!(buffer.len() <= std::u32::MAX as usize);
+            let mut attribute_len = buffer.len() as DWORD;
+
+            try_pcsc!(ffi::SCardGetAttrib(
+                self.handle,
+                attribute.into_raw(),
+                buffer.as_mut_ptr(),
+                &mut attribute_len,
+            ));
+
+            Ok(&buffer[0..attribute_len as usize])
+        }
+    }
+
+    /// Get several attributes of the card or card reader.
+    ///
+    /// The attributes are fetched in sequence, using a single scratch
+    /// buffer. A failure to get one attribute does not prevent getting
+    /// the others.
+    ///
+    /// Returns the result for each of `attributes`, in order.
+    pub fn get_attributes(
+        &self,
+        attributes: &[Attribute],
+    ) -> Vec<(Attribute, Result<Vec<u8>, Error>)> {
+        let mut buffer = Vec::new();
+        attributes.iter().map(|&attribute| {
+            let result = self.get_attribute_len(attribute).and_then(|len| {
+                if buffer.len() < len {
+                    buffer.resize(len, 0);
+                }
+                self.get_attribute(attribute, &mut buffer).map(|data| data.to_vec())
+            });
+            (attribute, result)
+        }).collect()
+    }
+
+    /// Get the needed length of a buffer to be passed to `get_attribute`.
+    ///
+    /// This function wraps `SCardGetAttrib` ([pcsclite][1], [MSDN][2]).
+    ///
+    /// [1]: https://pcsclite.apdu.fr/api/group__API.html#gaacfec51917255b7a25b94c5104961602
+    /// [2]: https://msdn.microsoft.com/en-us/library/aa379559.aspx
+    pub fn get_attribute_len(
+        &self,
+        attribute: Attribute,
+    ) -> Result<usize, Error> {
         unsafe {
-            let mut raw_status: DWORD = DUMMY_DWORD;
-            let mut raw_protocol: DWORD = DUMMY_DWORD;
+            let mut attribute_len = DUMMY_DWORD;
 
-            try_pcsc!(ffi::SCardStatus(
+            try_pcsc!(ffi::SCardGetAttrib(
                 self.handle,
+                attribute.into_raw(),
                 null_mut(),
-                null_mut(),
-                &mut raw_status,
-                &mut raw_protocol,
-                null_mut(),
-                null_mut(),
+                &mut attribute_len,
             ));
 
-            let status = Status::from_bits_truncate(raw_status);
-            let protocol = Protocol::from_raw(raw_protocol);
+            Ok(attribute_len as usize)
+        }
+    }
 
-            Ok((status, protocol))
+    // Get an attribute into a newly allocated buffer of the needed
+    // length.
+    fn get_attribute_vec(
+        &self,
+        attribute: Attribute,
+    ) -> Result<Vec<u8>, Error> {
+        let mut buffer = vec![0; self.get_attribute_len(attribute)?];
+        let len = self.get_attribute(attribute, &mut buffer)?.len();
+        buffer.truncate(len);
+        Ok(buffer)
+    }
+
+    // Get a string attribute. The terminating NUL, if any, is stripped.
+    fn get_attribute_cstring(
+        &self,
+        attribute: Attribute,
+    ) -> Result<CString, Error> {
+        let mut data = self.get_attribute_vec(attribute)?;
+        let nul_pos = data.iter().position(|&c| c == 0);
+        if let Some(nul_pos) = nul_pos {
+            data.truncate(nul_pos);
         }
+        // Can't fail, we cut at the first NUL.
+        Ok(CString::new(data).unwrap())
     }
 
-    /// Get an attribute of the card or card reader.
+    // Get an integer attribute. Integer attributes are encoded in
+    // little-endian, and are usually (but not always) 4 bytes long.
+    fn get_attribute_u32(
+        &self,
+        attribute: Attribute,
+    ) -> Result<u32, Error> {
+        let data = self.get_attribute_vec(attribute)?;
+        if data.is_empty() || data.iter().skip(4).any(|&b| b != 0) {
+            return Err(Error::InvalidValue);
+        }
+        Ok(data.iter().take(4).rev().fold(0, |acc, &b| (acc << 8) | u32::from(b)))
+    }
+
+    /// Check whether the card reader supports power management.
+    ///
+    /// If supported, the reader may power down the card, for example
+    /// when the system is suspended.
+    ///
+    /// This function decodes `Attribute::PowerMgmtSupport`.
+    pub fn power_mgmt_supported(
+        &self,
+    ) -> Result<bool, Error> {
+        Ok(self.get_attribute_u32(Attribute::PowerMgmtSupport)? != 0)
+    }
+
+    /// Get the channel through which the card reader is connected.
+    ///
+    /// This function decodes `Attribute::ChannelId`.
+    pub fn channel_id(
+        &self,
+    ) -> Result<ChannelId, Error> {
+        Ok(ChannelId::from_raw(self.get_attribute_u32(Attribute::ChannelId)?))
+    }
+
+    /// Get the protocols supported by the card reader.
+    ///
+    /// The asynchronous protocols T=0 and T=1 are taken from
+    /// `Attribute::AsyncProtocolTypes`. If the reader reports any
+    /// synchronous protocols in `Attribute::SyncProtocolTypes`,
+    /// `Protocols::RAW` is included as well, since synchronous cards are
+    /// accessed using the raw protocol.
+    ///
+    /// This can be used to check whether a `reconnect` with a different
+    /// protocol may succeed.
+    pub fn supported_protocols(
+        &self,
+    ) -> Result<Protocols, Error> {
+        let async_types = self.get_attribute_u32(Attribute::AsyncProtocolTypes)?;
+        let mut protocols = Protocols::empty();
+        if async_types & ASYNC_PROTOCOL_T0 != 0 {
+            protocols |= Protocols::T0;
+        }
+        if async_types & ASYNC_PROTOCOL_T1 != 0 {
+            protocols |= Protocols::T1;
+        }
+        // Readers which don't support synchronous cards usually don't
+        // report the attribute at all.
+        if let Ok(sync_types) = self.get_attribute_u32(Attribute::SyncProtocolTypes) {
+            if sync_types != 0 {
+                protocols |= Protocols::RAW;
+            }
+        }
+        Ok(protocols)
+    }
+
+    /// Get the current clock rate of the card, in kHz.
+    ///
+    /// This function decodes `Attribute::CurrentClk`.
+    pub fn current_clock(
+        &self,
+    ) -> Result<u32, Error> {
+        self.get_attribute_u32(Attribute::CurrentClk)
+    }
+
+    /// Get the current data rate of the card, in bits per second.
+    ///
+    /// The rate is derived from the current clock rate and the clock rate
+    /// conversion (F) and baud rate adjustment (D) factors, as
+    /// `clock * D / F`.
+    ///
+    /// This function decodes `Attribute::CurrentClk`, `Attribute::CurrentF`
+    /// and `Attribute::CurrentD`.
+    pub fn current_data_rate(
+        &self,
+    ) -> Result<u32, Error> {
+        let clock_khz = u64::from(self.current_clock()?);
+        let f = u64::from(self.get_attribute_u32(Attribute::CurrentF)?);
+        let d = u64::from(self.get_attribute_u32(Attribute::CurrentD)?);
+        if f == 0 {
+            return Err(Error::InvalidValue);
+        }
+        let rate = clock_khz * 1000 * d / f;
+        if rate > u64::from(std::u32::MAX) {
+            return Err(Error::InvalidValue);
+        }
+        Ok(rate as u32)
+    }
+
+    /// Get the negotiated information field size for the card (IFSD),
+    /// used with protocol T=1.
+    ///
+    /// The IFSD bounds the size of the information field of the blocks
+    /// the card may send. It is only meaningful when the active protocol
+    /// is T=1.
+    ///
+    /// This function decodes `Attribute::CurrentIfsd`.
+    pub fn negotiated_ifsd(
+        &self,
+    ) -> Result<u32, Error> {
+        self.get_attribute_u32(Attribute::CurrentIfsd)
+    }
+
+    /// Get the PIN pad capabilities of the card reader.
+    ///
+    /// The capabilities are gathered from the features reported by the
+    /// reader (the `GET_FEATURE_REQUEST` control code of the PC/SC
+    /// specification part 10), the reader's TLV properties, and the
+    /// `Attribute::Maxinput`, `Attribute::UserAuthInputDevice` and
+    /// `Attribute::UserToCardAuthDevice` attributes.
+    ///
+    /// Readers which do not support `GET_FEATURE_REQUEST` are reported as
+    /// having no PIN pad. Attributes which cannot be read are reported as
+    /// absent.
+    pub fn pinpad_capabilities(
+        &self,
+    ) -> Result<PinpadCaps, Error> {
+        let mut caps = PinpadCaps {
+            verify_pin_direct: false,
+            modify_pin_direct: false,
+            display: false,
+            auth_input_device: false,
+            max_pin_size: None,
+            max_input: self.get_attribute_u32(Attribute::Maxinput).ok(),
+        };
+
+        caps.auth_input_device = [Attribute::UserAuthInputDevice, Attribute::UserToCardAuthDevice]
+            .iter()
+            .any(|&attribute| self.get_attribute_u32(attribute).map(|value| value != 0).unwrap_or(false));
+
+        let mut buffer = [0; MAX_BUFFER_SIZE];
+        let mut tlv_properties_code = None;
+        match self.control(ctl_code(CM_IOCTL_GET_FEATURE_REQUEST), &[], &mut buffer) {
+            Ok(features) => {
+                // Each feature is a TLV, with the control code as a
+                // big-endian value.
+                for feature in tlv::parse_tlv(features) {
+                    let (tag, value) = match feature {
+                        Ok(feature) => feature,
+                        Err(_) => return Err(Error::InvalidValue),
+                    };
+                    match tag {
+                        FEATURE_VERIFY_PIN_DIRECT => caps.verify_pin_direct = true,
+                        FEATURE_MODIFY_PIN_DIRECT => caps.modify_pin_direct = true,
+                        FEATURE_IFD_DISPLAY_PROPERTIES => caps.display = true,
+                        FEATURE_GET_TLV_PROPERTIES if value.len() == 4 => {
+                            let code = value.iter().fold(0, |acc, &b| (acc << 8) | u32::from(b));
+                            tlv_properties_code = Some(code);
+                        },
+                        _ => {},
+                    }
+                }
+            },
+            Err(Error::UnsupportedFeature) => return Ok(caps),
+            Err(err) => return Err(err),
+        }
+
+        if let Some(code) = tlv_properties_code {
+            let properties = self.control(DWORD::from(code), &[], &mut buffer)?;
+            // The properties are TLVs with little-endian values.
+            for property in tlv::parse_tlv(properties) {
+                let (tag, value) = match property {
+                    Ok(property) => property,
+                    Err(_) => return Err(Error::InvalidValue),
+                };
+                match tag {
+                    PCSCV2_PART10_PROPERTY_W_LCD_LAYOUT if value.iter().any(|&b| b != 0) => {
+                        caps.display = true;
+                    },
+                    PCSCV2_PART10_PROPERTY_B_MAX_PIN_SIZE if value.len() == 1 => {
+                        caps.max_pin_size = Some(value[0]);
+                    },
+                    _ => {},
+                }
+            }
+        }
+
+        Ok(caps)
+    }
+
+    /// Get the current state of the I/O lines of the card reader.
+    ///
+    /// The meaning of the value is reader-specific; it is mostly useful
+    /// for diagnostics.
+    ///
+    /// This function decodes `Attribute::CurrentIoState`.
+    pub fn current_io_state(
+        &self,
+    ) -> Result<u32, Error> {
+        self.get_attribute_u32(Attribute::CurrentIoState)
+    }
+
+    /// Get the ATR (Answer To Reset) of the card.
+    ///
+    /// `buffer` should be at least `MAX_ATR_SIZE` bytes long. If the
+    /// reader returns an ATR longer than `MAX_ATR_SIZE`,
+    /// `Error::InvalidAtr` is returned.
+    ///
+    /// This function decodes `Attribute::AtrString`.
+    pub fn atr<'buf>(
+        &self,
+        buffer: &'buf mut [u8],
+    ) -> Result<&'buf [u8], Error> {
+        let atr = self.get_attribute(Attribute::AtrString, buffer)?;
+        if atr.len() > MAX_ATR_SIZE {
+            return Err(Error::InvalidAtr);
+        }
+        Ok(atr)
+    }
+
+    /// Get the serial number of the card reader, as a printable string.
+    ///
+    /// The serial number is usually ASCII, but some readers return binary
+    /// data. Trailing NUL bytes are removed; then, if the serial number is
+    /// valid UTF-8 without control characters, it is returned as is.
+    /// Otherwise, it is returned as uppercase hex digits, e.g. `"0A1B2C"`.
+    ///
+    /// This function decodes `Attribute::VendorIfdSerialNo`.
+    pub fn ifd_serial_string(
+        &self,
+    ) -> Result<String, Error> {
+        let mut data = self.get_attribute_vec(Attribute::VendorIfdSerialNo)?;
+        while data.last() == Some(&0) {
+            data.pop();
+        }
+
+        let bytes = match String::from_utf8(data) {
+            Ok(serial) => {
+                if !serial.chars().any(|c| c.is_control()) {
+                    return Ok(serial);
+                }
+                serial.into_bytes()
+            },
+            Err(err) => err.into_bytes(),
+        };
+        Ok(hex::encode(&bytes))
+    }
+
+    /// Get an identifier of the card reader which is stable across
+    /// replugs.
+    ///
+    /// Reader names include an index which may change when the reader is
+    /// replugged. This identifier is instead made of the vendor name
+    /// (`Attribute::VendorName`), reader type (`Attribute::VendorIfdType`)
+    /// and serial number (`ifd_serial_string`), separated by `/`, e.g.
+    /// `ACS/ACR1252U/A1B2C3`. Parts which the reader does not report are
+    /// left empty.
+    ///
+    /// If the reader does not report a serial number, the device unit
+    /// (`Attribute::DeviceUnit`) is used in its place, prefixed with `#`.
+    /// This distinguishes multiple readers of the same type, but is not
+    /// stable if they are replugged in a different order.
+    pub fn stable_id(
+        &self,
+    ) -> Result<String, Error> {
+        let vendor = self.get_attribute_cstring(Attribute::VendorName)
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let ifd_type = self.get_attribute_cstring(Attribute::VendorIfdType)
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let serial = match self.ifd_serial_string() {
+            Ok(ref serial) if !serial.is_empty() => serial.clone(),
+            _ => format!("#{}", self.get_attribute_u32(Attribute::DeviceUnit)?),
+        };
+        Ok(format!("{}/{}/{}", vendor, ifd_type, serial))
+    }
+
+    /// Get the friendly name of the card reader.
+    ///
+    /// The friendly name is meant for display to the user. It may not be
+    /// the same as the name returned by `Context::list_readers`, which
+    /// should be used with `Context::connect` instead.
+    ///
+    /// This function decodes `Attribute::DeviceFriendlyName`.
+    pub fn friendly_name(
+        &self,
+    ) -> Result<CString, Error> {
+        self.get_attribute_cstring(Attribute::DeviceFriendlyName)
+    }
+
+    /// Get the system name of the card reader.
+    ///
+    /// The system name is stable, and is the name which should be passed
+    /// to `Context::connect`. For display, prefer `friendly_name`.
+    ///
+    /// This function decodes `Attribute::DeviceSystemName`.
+    pub fn system_name(
+        &self,
+    ) -> Result<CString, Error> {
+        self.get_attribute_cstring(Attribute::DeviceSystemName)
+    }
+
+    /// Set an attribute of the card or card reader.
+    ///
+    /// This function wraps `SCardSetAttrib` ([pcsclite][1], [MSDN][2]).
+    ///
+    /// [1]: https://pcsclite.apdu.fr/api/group__API.html#ga060f0038a4ddfd5dd2b8fadf3c3a2e4f
+    /// [2]: https://msdn.microsoft.com/en-us/library/aa379801.aspx
+    pub fn set_attribute(
+        &self,
+        attribute: Attribute,
+        attribute_data: &[u8],
+    ) -> Result<(), Error> {
+        unsafe {
+            assert!(attribute_data.len() <= std::u32::MAX as usize);
+
+            try_pcsc!(ffi::SCardSetAttrib(
+                self.handle,
+                attribute.into_raw(),
+                attribute_data.as_ptr(),
+                attribute_data.len() as DWORD,
+            ));
+
+            Ok(())
+        }
+    }
+
+    // Set an integer attribute, encoded as a 4-byte little-endian value.
+    fn set_attribute_u32(
+        &self,
+        attribute: Attribute,
+        value: u32,
+    ) -> Result<(), Error> {
+        let data = [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8];
+        self.set_attribute(attribute, &data)
+    }
+
+    /// Check whether the reset escape command is enabled.
+    ///
+    /// This function decodes `Attribute::EscReset`.
+    pub fn esc_reset(
+        &self,
+    ) -> Result<bool, Error> {
+        Ok(self.get_attribute_u32(Attribute::EscReset)? != 0)
+    }
+
+    /// Enable or disable the reset escape command.
+    ///
+    /// Some readers require this before the corresponding escape
+    /// commands can be sent with `control`.
+    ///
+    /// This function encodes `Attribute::EscReset`.
+    pub fn set_esc_reset(
+        &self,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        self.set_attribute_u32(Attribute::EscReset, enabled as u32)
+    }
+
+    /// Check whether the cancel escape command is enabled.
+    ///
+    /// This function decodes `Attribute::EscCancel`.
+    pub fn esc_cancel(
+        &self,
+    ) -> Result<bool, Error> {
+        Ok(self.get_attribute_u32(Attribute::EscCancel)? != 0)
+    }
+
+    /// Enable or disable the cancel escape command.
+    ///
+    /// This function encodes `Attribute::EscCancel`.
+    pub fn set_esc_cancel(
+        &self,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        self.set_attribute_u32(Attribute::EscCancel, enabled as u32)
+    }
+
+    /// Check whether the authentication request escape command is enabled.
+    ///
+    /// This function decodes `Attribute::EscAuthrequest`.
+    pub fn esc_auth_request(
+        &self,
+    ) -> Result<bool, Error> {
+        Ok(self.get_attribute_u32(Attribute::EscAuthrequest)? != 0)
+    }
+
+    /// Enable or disable the authentication request escape command.
+    ///
+    /// This function encodes `Attribute::EscAuthrequest`.
+    pub fn set_esc_auth_request(
+        &self,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        self.set_attribute_u32(Attribute::EscAuthrequest, enabled as u32)
+    }
+
+    /// Transmit an APDU command to the card.
+    ///
+    /// `receive_buffer` is a buffer that should be large enough to hold
+    /// the APDU response.
+    ///
+    /// Returns a slice into `receive_buffer` containing the APDU
+    /// response.
+    ///
+    /// If `receive_buffer` is not large enough to hold the APDU response,
+    /// `Error::InsufficientBuffer` is returned.
+    ///
+    /// If `send_buffer` is shorter than an APDU header (4 bytes),
+    /// `Error::InvalidParameter` is returned without calling into the
+    /// PC/SC implementation.
+    ///
+    /// If the card was connected with `ShareMode::Direct`, no protocol is
+    /// active, and `Error::ProtoMismatch` is returned.
+    ///
+    /// The returned response borrows `receive_buffer`, so the buffer can
+    /// be reused for another command (also after a `reconnect`) only once
+    /// the response is no longer used. This is checked at compile time;
+    /// for example, the following does not compile:
+    ///
+    /// ```compile_fail
+    /// # use pcsc::*;
+    /// # fn f(card: &Card) {
+    /// let mut buffer = [0; MAX_BUFFER_SIZE];
+    /// let first = card.transmit(b"\x00\xa4\x04\x00", &mut buffer).unwrap();
+    /// let second = card.transmit(b"\x00\xb0\x00\x00", &mut buffer).unwrap();
+    /// assert_eq!(first, second);
+    /// # }
+    /// ```
+    ///
+    /// This function wraps `SCardTransmit` ([pcsclite][1], [MSDN][2]).
+    ///
+    /// [1]: https://pcsclite.apdu.fr/api/group__API.html#ga9a2d77242a271310269065e64633ab99
+    /// [2]: https://msdn.microsoft.com/en-us/library/aa379804.aspx
+    pub fn transmit<'buf>(
+        &self,
+        send_buffer: &[u8],
+        receive_buffer: &'buf mut [u8],
+    ) -> Result<&'buf [u8], Error> {
+        unsafe {
+            let receive_len = self.transmit_raw(
+                send_buffer,
+                receive_buffer.as_mut_ptr(),
+                receive_buffer.len(),
+            )?;
+
+            Ok(&receive_buffer[0..receive_len])
+        }
+    }
+
+    /// Transmit an APDU command to the card, checking its encoding first.
+    ///
+    /// This function is like `transmit`, but first checks that the length
+    /// of `send_buffer` is consistent with the Lc and Le fields of the
+    /// command, for both short and extended APDUs. If it is not,
+    /// `Error::InvalidValue` is returned without calling into the PC/SC
+    /// implementation. This catches encoding mistakes which otherwise
+    /// cause obscure errors from the driver or the card.
+    pub fn transmit_checked<'buf>(
+        &self,
+        send_buffer: &[u8],
+        receive_buffer: &'buf mut [u8],
+    ) -> Result<&'buf [u8], Error> {
+        if !is_valid_apdu_command(send_buffer) {
+            return Err(Error::InvalidValue);
+        }
+        self.transmit(send_buffer, receive_buffer)
+    }
+
+    /// Transmit an APDU command to the card, returning only the response
+    /// data.
+    ///
+    /// This function is like `transmit`, but the status word (the last 2
+    /// bytes of the APDU response) is stripped, and is not checked. It is
+    /// meant for callers which validate the status word by other means.
+    ///
+    /// If the APDU response is shorter than 2 bytes, `Error::InvalidValue`
+    /// is returned.
+    pub fn transmit_data<'buf>(
+        &self,
+        send_buffer: &[u8],
+        receive_buffer: &'buf mut [u8],
+    ) -> Result<&'buf [u8], Error> {
+        let response = self.transmit(send_buffer, receive_buffer)?;
+        if response.len() < 2 {
+            return Err(Error::InvalidValue);
+        }
+        Ok(&response[..response.len() - 2])
+    }
+
+    /// Transmit an APDU command to the card, converting the bytes for
+    /// readers which do not handle the inverse convention.
+    ///
+    /// A reader should handle the convention of the card transparently.
+    /// Some legacy readers do not, and exchange the bytes of a card using
+    /// the inverse convention as is; each byte is then received with its
+    /// bits reversed and complemented. If the ATR of the card indicates
+    /// the inverse convention, this function converts the command before
+    /// sending it, and the response after receiving it. Otherwise, it is
+    /// the same as `transmit`.
+    ///
+    /// Such readers also report the ATR unconverted, so that the initial
+    /// character (TS) of an inverse convention card is `03` instead of
+    /// `3F`, and the following bytes are likewise garbled. Both `03` and
+    /// `3F` are taken to indicate the inverse convention, and `3B` the
+    /// direct convention; any other TS fails with `Error::InvalidAtr`.
+    ///
+    /// Only use this function with readers which are known to have this
+    /// problem; with other readers, it garbles the data.
+    pub fn transmit_fix_convention<'buf>(
+        &self,
+        send_buffer: &[u8],
+        receive_buffer: &'buf mut [u8],
+    ) -> Result<&'buf [u8], Error> {
+        let inverse = match self.get_atr_vec()?.first() {
+            // 03 is the unconverted 3F.
+            Some(&0x03) | Some(&0x3F) => true,
+            Some(&0x3B) => false,
+            _ => return Err(Error::InvalidAtr),
+        };
+        if !inverse {
+            return self.transmit(send_buffer, receive_buffer);
+        }
+
+        let send_buffer: Vec<u8> = send_buffer.iter().map(|&b| convert_inverse_convention(b)).collect();
+        let receive_len = unsafe {
+            self.transmit_raw(
+                &send_buffer,
+                receive_buffer.as_mut_ptr(),
+                receive_buffer.len(),
+            )?
+        };
+        let response = &mut receive_buffer[0..receive_len];
+        for b in response.iter_mut() {
+            *b = convert_inverse_convention(*b);
+        }
+        Ok(response)
+    }
+
+    /// Transmit an APDU command to the card, receiving the APDU response
+    /// into a `Vec`.
+    ///
+    /// `receive_buffer` is cleared, and enough capacity is reserved in it
+    /// to hold any APDU response (`MAX_BUFFER_SIZE_EXTENDED`, unless
+    /// changed with `set_default_recv_capacity`). Since the allocation is
+    /// kept, reusing the same `Vec` across calls avoids allocating for
+    /// each command.
     ///
-    /// `buffer` is a buffer that should be large enough for the attribute
-    /// data. The function `get_attribute_len` can be used to find the
-    /// exact required length.
+    /// The command is transmitted only once: reserving the full capacity
+    /// up front means there is no need to retry with a larger buffer after
+    /// `Error::InsufficientBuffer`, which could execute a non-idempotent
+    /// command twice.
     ///
-    /// Returns a slice into `buffer` containing the attribute data.
+    /// On success, `receive_buffer` contains the APDU response, and its
+    /// length is returned. On error, `receive_buffer` is left empty. In
+    /// both cases its capacity is kept, so the next call does not
+    /// allocate:
     ///
-    /// If the buffer is not large enough, `Error::InsufficientBuffer` is
-    /// returned.
+    /// ```no_run
+    /// # use pcsc::*;
+    /// # fn f(card: &Card) -> Result<(), Error> {
+    /// let mut response = Vec::new();
+    /// card.transmit_into_vec(b"\x00\xa4\x04\x00\x00", &mut response)?;
+    /// println!("{}", hex::encode(&response));
+    /// let capacity = response.capacity();
+    /// card.transmit_into_vec(b"\x00\xb0\x00\x00\x00", &mut response)?;
+    /// println!("{}", hex::encode(&response));
+    /// assert_eq!(response.capacity(), capacity);
+    /// # Ok(())
+    /// # }
+    /// ```
     ///
-    /// This function wraps `SCardGetAttrib` ([pcsclite][1], [MSDN][2]).
+    /// This function wraps `SCardTransmit` ([pcsclite][1], [MSDN][2]).
     ///
-    /// [1]: https://pcsclite.apdu.fr/api/group__API.html#gaacfec51917255b7a25b94c5104961602
-    /// [2]: https://msdn.microsoft.com/en-us/library/aa379559.aspx
-    pub fn get_attribute<'buf>(
+    /// [1]: https://pcsclite.apdu.fr/api/group__API.html#ga9a2d77242a271310269065e64633ab99
+    /// [2]: https://msdn.microsoft.com/en-us/library/aa379804.aspx
+    pub fn transmit_into_vec(
         &self,
-        attribute: Attribute,
-        buffer: &'buf mut [u8],
-    ) -> Result<&'buf [u8], Error> {
+        send_buffer: &[u8],
+        receive_buffer: &mut Vec<u8>,
+    ) -> Result<usize, Error> {
+        receive_buffer.clear();
+        receive_buffer.reserve(self.default_recv_capacity);
+
         unsafe {
-            assert!(buffer.len() <= std::u32::MAX as usize);
-            let mut attribute_len = buffer.len() as DWORD;
+            let receive_len = self.transmit_raw(
+                send_buffer,
+                receive_buffer.as_mut_ptr(),
+                receive_buffer.capacity(),
+            )?;
 
-            try_pcsc!(ffi::SCardGetAttrib(
-                self.handle,
-                attribute.into_raw(),
-                buffer.as_mut_ptr(),
-                &mut attribute_len,
-            ));
+            // The first receive_len bytes were initialized by the call.
+            receive_buffer.set_len(receive_len);
 
-            Ok(&buffer[0..attribute_len as usize])
+            Ok(receive_len)
         }
     }
 
-    /// Get the needed length of a buffer to be passed to `get_attribute`.
-    ///
-    /// This function wraps `SCardGetAttrib` ([pcsclite][1], [MSDN][2]).
+    /// Transmit an APDU command to the card, expecting success.
     ///
-    /// [1]: https://pcsclite.apdu.fr/api/group__API.html#gaacfec51917255b7a25b94c5104961602
-    /// [2]: https://msdn.microsoft.com/en-us/library/aa379559.aspx
-    pub fn get_attribute_len(
+    /// This function is like `transmit_data`, but additionally checks
+    /// the status word. If it is not `90 00`, `HelperError::Apdu` is
+    /// returned with the actual status word.
+    pub fn transmit_expect_ok<'buf>(
         &self,
-        attribute: Attribute,
-    ) -> Result<usize, Error> {
-        unsafe {
-            let mut attribute_len = DUMMY_DWORD;
+        send_buffer: &[u8],
+        receive_buffer: &'buf mut [u8],
+    ) -> Result<&'buf [u8], HelperError> {
+        let response = self.transmit(send_buffer, receive_buffer)?;
+        if response.len() < 2 {
+            return Err(Error::InvalidValue.into());
+        }
+        let (data, sw) = response.split_at(response.len() - 2);
+        let sw = iso7816::StatusWord::from_bytes(sw[0], sw[1]);
+        if !sw.is_success() {
+            return Err(ApduError::new(sw).into());
+        }
+        Ok(data)
+    }
 
-            try_pcsc!(ffi::SCardGetAttrib(
-                self.handle,
-                attribute.into_raw(),
-                null_mut(),
-                &mut attribute_len,
-            ));
+    /// Set the receive buffer capacity used by the functions which
+    /// receive the APDU response into a `Vec`, such as
+    /// `transmit_into_vec` and `transmit_apdu_owned`.
+    ///
+    /// The default is `MAX_BUFFER_SIZE_EXTENDED`, which can hold any APDU
+    /// response. On memory-constrained targets, a smaller capacity, such
+    /// as `MAX_BUFFER_SIZE`, can be used if extended APDUs are not needed;
+    /// responses which do not fit fail with `Error::InsufficientBuffer`.
+    pub fn set_default_recv_capacity(
+        &mut self,
+        capacity: usize,
+    ) {
+        self.default_recv_capacity = capacity;
+    }
 
-            Ok(attribute_len as usize)
-        }
+    /// Get the receive buffer capacity set by
+    /// `set_default_recv_capacity`.
+    pub fn default_recv_capacity(
+        &self,
+    ) -> usize {
+        self.default_recv_capacity
     }
 
-    /// Set an attribute of the card or card reader.
+    /// Transmit an APDU command to the card, returning the response data
+    /// and the status word.
     ///
-    /// This function wraps `SCardSetAttrib` ([pcsclite][1], [MSDN][2]).
+    /// The APDU response is received into a newly allocated `Vec`, as in
+    /// `transmit_into_vec`. The status word (the last 2 bytes of the
+    /// response) is split off and returned as a `u16`, e.g. `0x9000`.
     ///
-    /// [1]: https://pcsclite.apdu.fr/api/group__API.html#ga060f0038a4ddfd5dd2b8fadf3c3a2e4f
-    /// [2]: https://msdn.microsoft.com/en-us/library/aa379801.aspx
-    pub fn set_attribute(
+    /// If the APDU response is shorter than 2 bytes, `Error::InvalidValue`
+    /// is returned.
+    pub fn transmit_apdu_owned(
         &self,
-        attribute: Attribute,
-        attribute_data: &[u8],
-    ) -> Result<(), Error> {
-        unsafe {
-            assert!(attribute_data.len() <= std::u32::MAX as usize);
+        send_buffer: &[u8],
+    ) -> Result<(Vec<u8>, u16), Error> {
+        let mut response = Vec::new();
+        let len = self.transmit_into_vec(send_buffer, &mut response)?;
+        if len < 2 {
+            return Err(Error::InvalidValue);
+        }
+        let sw = (u16::from(response[len - 2]) << 8) | u16::from(response[len - 1]);
+        response.truncate(len - 2);
+        response.shrink_to_fit();
+        Ok((response, sw))
+    }
 
-            try_pcsc!(ffi::SCardSetAttrib(
-                self.handle,
-                attribute.into_raw(),
-                attribute_data.as_ptr(),
-                attribute_data.len() as DWORD,
-            ));
+    /// Transmit an APDU command given as a hex string to the card,
+    /// returning the response data and the status word.
+    ///
+    /// The string is decoded with `hex::decode`, e.g. `"00 A4 04 00 00"`.
+    /// If it is not valid hex, `Error::InvalidParameter` is returned.
+    ///
+    /// See `transmit_apdu_owned`.
+    pub fn transmit_hex(
+        &self,
+        command: &str,
+    ) -> Result<(Vec<u8>, u16), Error> {
+        let command = match hex::decode(command) {
+            Ok(command) => command,
+            Err(_) => return Err(Error::InvalidParameter),
+        };
+        self.transmit_apdu_owned(&command)
+    }
 
-            Ok(())
+    /// Transmit an APDU command to the card, growing the receive buffer
+    /// as needed.
+    ///
+    /// The receive buffer starts at `MAX_BUFFER_SIZE` bytes (or
+    /// `max_capacity`, if smaller). Whenever the APDU response does not
+    /// fit, the buffer size is doubled, up to `max_capacity` bytes, and
+    /// the command is transmitted again. If the response does not fit in
+    /// `max_capacity` bytes, `Error::InsufficientBuffer` is returned.
+    ///
+    /// Returns the APDU response.
+    ///
+    /// ## Note
+    ///
+    /// Growing the buffer requires sending the command again, so this
+    /// function should only be used with commands which are safe to
+    /// repeat (e.g. reading data, not incrementing a counter).
+    pub fn transmit_growing(
+        &self,
+        send_buffer: &[u8],
+        max_capacity: usize,
+    ) -> Result<Vec<u8>, Error> {
+        let mut capacity = std::cmp::min(MAX_BUFFER_SIZE, max_capacity);
+        loop {
+            let mut receive_buffer = vec![0; capacity];
+            match self.transmit(send_buffer, &mut receive_buffer) {
+                Ok(response) => return Ok(response.to_vec()),
+                Err(Error::InsufficientBuffer) if capacity < max_capacity => {
+                    capacity = std::cmp::min(capacity.saturating_mul(2), max_capacity);
+                }
+                Err(err) => return Err(err),
+            }
         }
     }
 
-    /// Transmit an APDU command to the card.
+    /// Transmit an APDU command to the card, also receiving protocol
+    /// control information (PCI).
     ///
-    /// `receive_buffer` is a buffer that should be large enough to hold
+    /// This function is like `transmit`, but additionally fills
+    /// `recv_pci` with the protocol control information returned with
     /// the APDU response.
     ///
-    /// Returns a slice into `receive_buffer` containing the APDU
-    /// response.
-    ///
-    /// If `receive_buffer` is not large enough to hold the APDU response,
-    /// `Error::InsufficientBuffer` is returned.
+    /// Protocol-specific bytes which the reader returns after the PCI
+    /// header (e.g. with some T=1 readers) are available from
+    /// `RecvPci::extra_bytes` after the call. To receive them, `recv_pci`
+    /// must be created with enough extra capacity.
     ///
     /// This function wraps `SCardTransmit` ([pcsclite][1], [MSDN][2]).
     ///
     /// [1]: https://pcsclite.apdu.fr/api/group__API.html#ga9a2d77242a271310269065e64633ab99
     /// [2]: https://msdn.microsoft.com/en-us/library/aa379804.aspx
-    pub fn transmit<'buf>(
+    pub fn transmit_with_pci<'buf>(
         &self,
         send_buffer: &[u8],
+        recv_pci: &mut RecvPci,
         receive_buffer: &'buf mut [u8],
     ) -> Result<&'buf [u8], Error> {
-        let send_pci = get_protocol_pci(self.active_protocol);
-        let recv_pci = null_mut();
-        assert!(receive_buffer.len() <= std::u32::MAX as usize);
-        let mut receive_len = receive_buffer.len() as DWORD;
+        recv_pci.reset();
 
         unsafe {
-            assert!(send_buffer.len() <= std::u32::MAX as usize);
-
-            try_pcsc!(ffi::SCardTransmit(
-                self.handle,
-                send_pci,
-                send_buffer.as_ptr(),
-                send_buffer.len() as DWORD,
-                recv_pci,
+            let receive_len = self.transmit_raw_pci(
+                send_buffer,
+                recv_pci.as_mut_ptr(),
                 receive_buffer.as_mut_ptr(),
-                &mut receive_len,
-            ));
+                receive_buffer.len(),
+            )?;
 
-            Ok(&receive_buffer[0..receive_len as usize])
+            Ok(&receive_buffer[0..receive_len])
+        }
+    }
+
+    /// Transmit an APDU command to the card, also receiving protocol
+    /// control information (PCI), and verify its protocol.
+    ///
+    /// This function is like `transmit_with_pci`, but additionally checks
+    /// that the protocol in the received PCI is the active protocol of the
+    /// connection, which the command was sent with. If it is not,
+    /// `Error::ProtoMismatch` is returned; such a mismatch usually
+    /// indicates a driver bug. Readers which do not fill in the protocol
+    /// of the received PCI are not considered mismatched.
+    ///
+    /// ## Note
+    ///
+    /// The protocol can only be checked after the card executed the
+    /// command, and on a mismatch the response is discarded. For commands
+    /// which are not safe to repeat (e.g. incrementing a counter), use
+    /// `transmit_with_pci` and check `RecvPci::protocol` instead, to keep
+    /// the response.
+    pub fn transmit_with_pci_checked<'buf>(
+        &self,
+        send_buffer: &[u8],
+        recv_pci: &mut RecvPci,
+        receive_buffer: &'buf mut [u8],
+    ) -> Result<&'buf [u8], Error> {
+        let response = self.transmit_with_pci(send_buffer, recv_pci, receive_buffer)?;
+        check_recv_pci_protocol(recv_pci, self.protocol())?;
+        Ok(response)
+    }
+
+    // Transmit into a raw receive buffer of `receive_capacity` bytes.
+    // Returns the length of the APDU response.
+    unsafe fn transmit_raw(
+        &self,
+        send_buffer: &[u8],
+        receive_ptr: *mut u8,
+        receive_capacity: usize,
+    ) -> Result<usize, Error> {
+        self.transmit_raw_pci(send_buffer, null_mut(), receive_ptr, receive_capacity)
+    }
+
+    unsafe fn transmit_raw_pci(
+        &self,
+        send_buffer: &[u8],
+        recv_pci: *mut ffi::SCARD_IO_REQUEST,
+        receive_ptr: *mut u8,
+        receive_capacity: usize,
+    ) -> Result<usize, Error> {
+        // Drivers report this case inconsistently, so catch it here.
+        if send_buffer.len() < APDU_HEADER_SIZE {
+            return Err(Error::InvalidParameter);
         }
+
+        // With a direct connection, there is no protocol to transmit with.
+        let send_pci = match self.protocol() {
+            Some(protocol) => get_protocol_pci(protocol),
+            None => return Err(Error::ProtoMismatch),
+        };
+        assert!(receive_capacity <= std::u32::MAX as usize);
+        let mut receive_len = receive_capacity as DWORD;
+
+        assert!(send_buffer.len() <= std::u32::MAX as usize);
+
+        self.transmit_count.fetch_add(1, Ordering::Relaxed);
+        try_pcsc!(ffi::SCardTransmit(
+            self.handle,
+            send_pci,
+            send_buffer.as_ptr(),
+            send_buffer.len() as DWORD,
+            recv_pci,
+            receive_ptr,
+            &mut receive_len,
+        ));
+
+        Ok(receive_len as usize)
+    }
+
+    /// Get the number of APDU commands transmitted with this `Card`.
+    ///
+    /// Every call to `SCardTransmit` made by this `Card` is counted,
+    /// whether it succeeded or not. The count wraps around on overflow.
+    pub fn transmit_count(
+        &self,
+    ) -> u32 {
+        self.transmit_count.load(Ordering::Relaxed) as u32
+    }
+
+    /// Run `f`, and count the APDU commands transmitted with this `Card`
+    /// while it runs.
+    ///
+    /// Returns the result of `f` along with the difference in
+    /// `transmit_count`. This is useful for verifying the behavior of
+    /// retries or command chaining.
+    pub fn measure_transmits<T, F>(
+        &self,
+        f: F,
+    ) -> (T, u32)
+        where F: FnOnce(&Card) -> T {
+        let before = self.transmit_count();
+        let result = f(self);
+        let after = self.transmit_count();
+        (result, after.wrapping_sub(before))
     }
 
     /// Sends a command directly to the reader (driver).
@@ -1322,6 +5018,63 @@ impl<'tx> Transaction<'tx> {
             Ok(())
         }
     }
+
+    /// End the transaction, and reconnect to the card.
+    ///
+    /// The transaction is ended using `Disposition::LeaveCard`, and the
+    /// card is then reconnected as with `Card::reconnect`, with
+    /// `initialization` applied to the card. This is useful to change the
+    /// share mode after a transaction, for example to give up exclusive
+    /// access.
+    ///
+    /// If ending the transaction fails, ownership of the transaction is
+    /// returned to the caller along with the error, as with `end`, and the
+    /// card is not reconnected. If reconnecting fails, the transaction is
+    /// already ended, and only the error is returned.
+    ///
+    /// ```no_run
+    /// # use pcsc::*;
+    /// # fn f(card: &mut Card) -> Result<(), Error> {
+    /// let tx = card.transaction()?;
+    /// // ... exchange APDUs ...
+    /// match tx.end_and_reconnect(ShareMode::Shared, Protocols::ANY, Disposition::LeaveCard) {
+    ///     Ok(()) => {}
+    ///     // The transaction is still active; it can be used again, or
+    ///     // dropped to end it with `Disposition::LeaveCard`.
+    ///     Err((Some(tx), err)) => {
+    ///         println!("failed to end the transaction: {}", err);
+    ///         drop(tx);
+    ///     }
+    ///     // The transaction ended, but reconnecting failed.
+    ///     Err((None, err)) => return Err(err),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn end_and_reconnect(
+        self,
+        share_mode: ShareMode,
+        preferred_protocols: Protocols,
+        initialization: Disposition,
+    ) -> Result<(), (Option<Transaction<'tx>>, Error)> {
+        unsafe {
+            let err = ffi::SCardEndTransaction(
+                self.card.handle,
+                Disposition::LeaveCard.into_raw(),
+            );
+            if err != 0 {
+                return Err((Some(self), Error::from_raw(err)));
+            }
+
+            // Take back the card, skipping the drop since we ended the
+            // transaction "manually".
+            let card = std::ptr::read(&self.card);
+            forget(self);
+
+            card.reconnect(share_mode, preferred_protocols, initialization)
+                .map_err(|err| (None, err))
+        }
+    }
 }
 
 impl<'tx> Drop for Transaction<'tx> {
@@ -1347,3 +5100,418 @@ impl<'tx> Deref for Transaction<'tx> {
         self.card
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cstr(bytes: &[u8]) -> &CStr {
+        CStr::from_bytes_with_nul(bytes).unwrap()
+    }
+
+    fn reader_names(buf: &[u8]) -> Vec<&CStr> {
+        ReaderNames {
+            buf,
+            pos: 0,
+        }.collect()
+    }
+
+    #[test]
+    fn reader_names_well_formed() {
+        let names = reader_names(b"Reader A\0Reader B\0\0");
+        assert_eq!(names, [cstr(b"Reader A\0"), cstr(b"Reader B\0")]);
+        assert!(reader_names(b"\0").is_empty());
+    }
+
+    #[test]
+    fn reader_names_empty_buffer() {
+        assert!(reader_names(b"").is_empty());
+    }
+
+    #[test]
+    fn reader_names_missing_final_nul() {
+        // The unterminated name is dropped.
+        let names = reader_names(b"Reader A\0Reader B");
+        assert_eq!(names, [cstr(b"Reader A\0")]);
+        assert!(reader_names(b"Reader A").is_empty());
+    }
+
+    #[test]
+    fn reader_names_missing_double_nul() {
+        let names = reader_names(b"Reader A\0Reader B\0");
+        assert_eq!(names, [cstr(b"Reader A\0"), cstr(b"Reader B\0")]);
+    }
+
+    #[test]
+    fn reader_names_exhausted() {
+        let mut names = ReaderNames {
+            buf: b"Reader A",
+            pos: 0,
+        };
+        assert_eq!(names.next(), None);
+        assert_eq!(names.next(), None);
+    }
+
+    #[test]
+    fn reader_states_sync_then_add() {
+        let mut readers = ReaderStates::new();
+        assert!(readers.add(cstr(b"Reader A\0")));
+        // Simulate a reported card insertion.
+        readers[0].inner.dwEventState = (State::CHANGED | State::PRESENT).bits() | (1 << 16);
+        readers.sync_all();
+
+        assert!(readers.add(cstr(b"Reader B\0")));
+        assert!(!readers.add(cstr(b"Reader A\0")));
+        assert_eq!(readers.len(), 2);
+
+        assert!(readers[0].has_name(cstr(b"Reader A\0")));
+        assert_eq!(readers[0].inner.dwCurrentState, (State::CHANGED | State::PRESENT).bits() | (1 << 16));
+        assert_eq!(readers[0].event_count(), 1);
+
+        assert!(readers[1].has_name(cstr(b"Reader B\0")));
+        assert_eq!(readers[1].inner.dwCurrentState, State::UNAWARE.bits());
+        assert_eq!(readers[1].event_state(), State::UNAWARE);
+    }
+
+    #[test]
+    fn guid_round_trip() {
+        let bytes = [
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77,
+            0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF,
+        ];
+        let guid = Guid::from_bytes(bytes);
+        let s = guid.to_string();
+        assert_eq!(s, "{00112233-4455-6677-8899-AABBCCDDEEFF}");
+        assert_eq!(s.parse::<Guid>(), Ok(guid));
+        assert_eq!(guid.as_bytes(), &bytes);
+    }
+
+    #[test]
+    fn guid_parse_variants() {
+        let guid = "{00112233-4455-6677-8899-AABBCCDDEEFF}".parse::<Guid>().unwrap();
+        assert_eq!("00112233-4455-6677-8899-AABBCCDDEEFF".parse::<Guid>(), Ok(guid));
+        assert_eq!("{00112233-4455-6677-8899-aabbccddeeff}".parse::<Guid>(), Ok(guid));
+    }
+
+    #[test]
+    fn guid_parse_bad_braces() {
+        assert_eq!("{00112233-4455-6677-8899-AABBCCDDEEFF".parse::<Guid>(), Err(ParseGuidError));
+        assert_eq!("00112233-4455-6677-8899-AABBCCDDEEFF}".parse::<Guid>(), Err(ParseGuidError));
+        assert_eq!("{{00112233-4455-6677-8899-AABBCCDDEEFF}}".parse::<Guid>(), Err(ParseGuidError));
+        assert_eq!("{}".parse::<Guid>(), Err(ParseGuidError));
+        assert_eq!("".parse::<Guid>(), Err(ParseGuidError));
+    }
+
+    #[test]
+    fn guid_parse_bad_groups() {
+        // Wrong group lengths.
+        assert_eq!("0011223-34455-6677-8899-AABBCCDDEEFF".parse::<Guid>(), Err(ParseGuidError));
+        assert_eq!("00112233-4455-6677-8899-AABBCCDDEEF".parse::<Guid>(), Err(ParseGuidError));
+        // Wrong number of groups.
+        assert_eq!("00112233-4455-6677-8899AABBCCDDEEFF".parse::<Guid>(), Err(ParseGuidError));
+        assert_eq!("00112233-4455-6677-8899-AABB-CCDDEEFF".parse::<Guid>(), Err(ParseGuidError));
+    }
+
+    #[test]
+    fn guid_parse_non_hex() {
+        assert_eq!("0011223G-4455-6677-8899-AABBCCDDEEFF".parse::<Guid>(), Err(ParseGuidError));
+        assert_eq!("00112233-4455-6677-8899-AABBCCDDEE+F".parse::<Guid>(), Err(ParseGuidError));
+    }
+
+    // ATR of a YubiKey 5 NFC: TD1 and TD2 chain to T=1 interface bytes.
+    const YUBIKEY_ATR: &[u8] = b"\x3B\xFD\x13\x00\x00\x81\x31\xFE\x15\x80\x73\xC0\x21\xC0\x57\x59\x75\x62\x69\x4B\x65\x79\x40";
+    // ATR of a MIFARE Classic 1K as reported by PC/SC contactless readers.
+    const MIFARE_ATR: &[u8] = b"\x3B\x8F\x80\x01\x80\x4F\x0C\xA0\x00\x00\x03\x06\x03\x00\x01\x00\x00\x00\x00\x6A";
+
+    #[test]
+    fn atr_historical_bytes_td_chain() {
+        assert_eq!(atr_historical_bytes(YUBIKEY_ATR), Some(&YUBIKEY_ATR[9..22]));
+        assert_eq!(atr_historical_bytes(MIFARE_ATR), Some(&MIFARE_ATR[4..19]));
+    }
+
+    #[test]
+    fn atr_historical_bytes_no_interface_bytes() {
+        assert_eq!(atr_historical_bytes(b"\x3B\x02\x14\x50"), Some(&b"\x14\x50"[..]));
+        assert_eq!(atr_historical_bytes(b"\x3B\x00"), Some(&b""[..]));
+    }
+
+    #[test]
+    fn atr_historical_bytes_truncated() {
+        assert_eq!(atr_historical_bytes(b""), None);
+        assert_eq!(atr_historical_bytes(b"\x3B"), None);
+        // In the interface bytes, before TD1 and TD2.
+        assert_eq!(atr_historical_bytes(&YUBIKEY_ATR[..4]), None);
+        assert_eq!(atr_historical_bytes(&YUBIKEY_ATR[..5]), None);
+        // In the historical bytes.
+        assert_eq!(atr_historical_bytes(&YUBIKEY_ATR[..15]), None);
+        // Without TCK, which is not needed.
+        assert!(atr_historical_bytes(&YUBIKEY_ATR[..22]).is_some());
+    }
+
+    #[test]
+    fn apdu_command_short_cases() {
+        // Case 1.
+        assert!(is_valid_apdu_command(b"\x00\xA4\x04\x00"));
+        // Case 2S.
+        assert!(is_valid_apdu_command(b"\x00\xB0\x00\x00\x00"));
+        // Case 3S.
+        assert!(is_valid_apdu_command(b"\x00\xA4\x04\x00\x02\x3F\x00"));
+        // Case 4S.
+        assert!(is_valid_apdu_command(b"\x00\xA4\x04\x00\x02\x3F\x00\x00"));
+    }
+
+    #[test]
+    fn apdu_command_extended_cases() {
+        // Case 2E.
+        assert!(is_valid_apdu_command(b"\x00\xB0\x00\x00\x00\x01\x00"));
+        // Case 3E.
+        let mut command = b"\x00\xD6\x00\x00\x00\x01\x00".to_vec();
+        command.extend_from_slice(&[0x55; 0x100]);
+        assert!(is_valid_apdu_command(&command));
+        // Case 4E.
+        command.extend_from_slice(b"\x00\x00");
+        assert!(is_valid_apdu_command(&command));
+    }
+
+    #[test]
+    fn apdu_command_malformed() {
+        // Shorter than the header.
+        assert!(!is_valid_apdu_command(b""));
+        assert!(!is_valid_apdu_command(b"\x00\xA4\x04"));
+        // Lc larger or smaller than the data.
+        assert!(!is_valid_apdu_command(b"\x00\xA4\x04\x00\x03\x3F\x00"));
+        assert!(!is_valid_apdu_command(b"\x00\xA4\x04\x00\x01\x3F\x00\x00"));
+        // Extended Lc of zero, and extended Lc larger than the data.
+        assert!(!is_valid_apdu_command(b"\x00\xD6\x00\x00\x00\x00\x00\x55"));
+        assert!(!is_valid_apdu_command(b"\x00\xD6\x00\x00\x00\x00\x02\x55"));
+        // Two bytes after the header is neither short nor extended.
+        assert!(!is_valid_apdu_command(b"\x00\xB0\x00\x00\x00\x00"));
+        // Case 4E with a short Le.
+        let mut command = b"\x00\xD6\x00\x00\x00\x00\x01\x55".to_vec();
+        assert!(is_valid_apdu_command(&command));
+        command.push(0x00);
+        assert!(!is_valid_apdu_command(&command));
+    }
+
+    fn normalize(name: &str) -> String {
+        normalize_reader_name(&CString::new(name).unwrap()).into_owned()
+    }
+
+    #[test]
+    fn normalize_reader_name_pcsclite() {
+        assert_eq!(normalize("Alcor Micro AU9560 00 00"), "Alcor Micro AU9560");
+        assert_eq!(normalize("Yubico YubiKey OTP+FIDO+CCID 01 00"), "Yubico YubiKey OTP+FIDO+CCID");
+        assert_eq!(normalize("ACS ACR122U PICC Interface 00 00"), "ACS ACR122U");
+        assert_eq!(normalize("SCM Microsystems Inc. SCR 3310 [CCID Interface] 00 00"), "SCM Microsystems Inc. SCR 3310");
+        assert_eq!(normalize("Gemalto PC Twin Reader (645D94C3) 00 00"), "Gemalto PC Twin Reader");
+    }
+
+    #[test]
+    fn normalize_reader_name_windows() {
+        assert_eq!(normalize("ACS ACR122U 0"), "ACS ACR122U");
+        assert_eq!(normalize("Alcor Micro USB Smart Card Reader 1"), "Alcor Micro USB Smart Card Reader");
+    }
+
+    #[test]
+    fn normalize_reader_name_without_suffix() {
+        assert_eq!(normalize("Generic Smart Card Reader"), "Generic Smart Card Reader");
+        assert_eq!(normalize("Reader"), "Reader");
+        assert_eq!(normalize("Reader  "), "Reader");
+        assert_eq!(normalize(""), "");
+
+        let name = CString::new("Generic Smart Card Reader").unwrap();
+        match normalize_reader_name(&name) {
+            Cow::Borrowed(normalized) => assert_eq!(normalized, "Generic Smart Card Reader"),
+            Cow::Owned(_) => panic!("valid UTF-8 should not be copied"),
+        }
+    }
+
+    #[test]
+    fn recv_pci_size() {
+        let dword_size = std::mem::size_of::<DWORD>();
+        for extra_capacity in 0..2 * dword_size + 1 {
+            let recv_pci = RecvPci::new(extra_capacity);
+            let size = recv_pci.buf.len() * dword_size;
+            assert!(size >= io_request_size() + extra_capacity);
+            assert!(size < io_request_size() + extra_capacity + dword_size);
+            assert_eq!(recv_pci.header().cbPciLength as usize, io_request_size() + extra_capacity);
+            assert_eq!(recv_pci.extra_bytes().len(), extra_capacity);
+        }
+    }
+
+    #[test]
+    fn scope_round_trip() {
+        for &scope in &[Scope::User, Scope::Terminal, Scope::System, Scope::Global] {
+            assert_eq!(Scope::from_raw(scope.into_raw()), Some(scope));
+            assert_eq!(Scope::from_raw(DWORD::from(scope as u32)), Some(scope));
+        }
+        assert_eq!(Scope::from_raw(ffi::SCARD_SCOPE_USER), Some(Scope::User));
+        assert_eq!(Scope::from_raw(ffi::SCARD_SCOPE_SYSTEM), Some(Scope::System));
+    }
+
+    #[test]
+    fn scope_unknown() {
+        assert_eq!(Scope::from_raw(0x1234), None);
+        assert_eq!(Scope::from_raw(DUMMY_DWORD), None);
+    }
+
+    #[test]
+    fn error_is_daemon_unavailable() {
+        assert!(Error::NoService.is_daemon_unavailable());
+        assert!(Error::ServiceStopped.is_daemon_unavailable());
+        assert!(Error::Shutdown.is_daemon_unavailable());
+
+        assert!(!Error::NoReadersAvailable.is_daemon_unavailable());
+        assert!(!Error::Timeout.is_daemon_unavailable());
+        assert!(!Error::CommError.is_daemon_unavailable());
+    }
+
+    #[test]
+    fn error_exit_code() {
+        let table = [
+            (Error::UnknownReader, 2),
+            (Error::NoReadersAvailable, 2),
+            (Error::NoSmartcard, 3),
+            (Error::RemovedCard, 3),
+            (Error::SharingViolation, 4),
+            (Error::CommError, 5),
+            (Error::ResetCard, 5),
+            (Error::NoService, 6),
+            (Error::ServiceStopped, 6),
+            (Error::Shutdown, 6),
+            (Error::Timeout, 7),
+            (Error::Cancelled, 7),
+            (Error::InvalidParameter, 1),
+            (Error::InternalError, 1),
+        ];
+        for &(err, code) in table.iter() {
+            assert_eq!(err.exit_code(), code, "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn recv_pci_protocol_check() {
+        let mut recv_pci = RecvPci::new(0);
+        // Not filled in by the reader.
+        assert_eq!(check_recv_pci_protocol(&recv_pci, Some(Protocol::T1)), Ok(()));
+
+        recv_pci.header_mut().dwProtocol = ffi::SCARD_PROTOCOL_T1;
+        assert_eq!(check_recv_pci_protocol(&recv_pci, Some(Protocol::T1)), Ok(()));
+        assert_eq!(check_recv_pci_protocol(&recv_pci, Some(Protocol::T0)), Err(Error::ProtoMismatch));
+        assert_eq!(check_recv_pci_protocol(&recv_pci, None), Err(Error::ProtoMismatch));
+    }
+
+    #[test]
+    fn card_capabilities_category_80() {
+        // Card capabilities (tag 7) after a card service data object (tag 3).
+        let historical = b"\x80\x31\xC0\x73\xC0\x21\x40";
+        assert_eq!(card_capabilities_from_historical(historical), Ok(CardCapabilities {
+            selection_methods: 0xC0,
+            data_coding: Some(0x21),
+            flags: Some(0x40),
+        }));
+    }
+
+    #[test]
+    fn card_capabilities_category_00() {
+        // The status indicator trailer is not parsed as a data object.
+        let historical = b"\x00\x71\xC0\x00\x90\x00";
+        assert_eq!(card_capabilities_from_historical(historical), Ok(CardCapabilities {
+            selection_methods: 0xC0,
+            data_coding: None,
+            flags: None,
+        }));
+        // Too short for the trailer.
+        assert_eq!(card_capabilities_from_historical(b"\x00\x90\x00"), Err(Error::UnsupportedFeature));
+    }
+
+    #[test]
+    fn card_capabilities_missing() {
+        assert_eq!(card_capabilities_from_historical(b"\x80\x31\xC0"), Err(Error::UnsupportedFeature));
+        assert_eq!(card_capabilities_from_historical(b"\x80"), Err(Error::UnsupportedFeature));
+        assert_eq!(card_capabilities_from_historical(b""), Err(Error::UnsupportedFeature));
+        // Unsupported category indicator.
+        assert_eq!(card_capabilities_from_historical(b"\x10\x73\xC0\x21\x40"), Err(Error::UnsupportedFeature));
+    }
+
+    #[test]
+    fn card_capabilities_overrun() {
+        assert_eq!(card_capabilities_from_historical(b"\x80\x73\xC0\x21"), Err(Error::InvalidAtr));
+        assert_eq!(card_capabilities_from_historical(b"\x80\x31\xC0\x7F"), Err(Error::InvalidAtr));
+    }
+
+    #[test]
+    fn recv_pci_reset() {
+        let mut recv_pci = RecvPci::new(5);
+        for dword in recv_pci.buf.iter_mut() {
+            *dword = !0;
+        }
+        recv_pci.reset();
+        assert_eq!(recv_pci.protocol(), None);
+        assert_eq!(recv_pci.extra_bytes(), &[0; 5][..]);
+        assert!(recv_pci.buf[io_request_size() / std::mem::size_of::<DWORD>()..].iter().all(|&dword| dword == 0));
+    }
+
+    #[test]
+    fn channel_id_from_raw() {
+        assert_eq!(ChannelId::from_raw(0x0020_0103), ChannelId::Usb(0x0103));
+        assert_eq!(ChannelId::from_raw(0x0001_0002), ChannelId::Serial(2));
+        assert_eq!(ChannelId::from_raw(0x00F0_0001), ChannelId::Vendor(0xF0, 1));
+        assert_eq!(ChannelId::from_raw(0x00FF_0007), ChannelId::Vendor(0xFF, 7));
+        assert_eq!(ChannelId::from_raw(0x0040_0001), ChannelId::Unknown(0x40, 1));
+    }
+
+    #[test]
+    fn reader_interface_markers() {
+        assert_eq!(reader_interface(cstr(b"ACS ACR1252 Dual Reader [ACR1252 Dual Reader PICC] 00 00\0")), ReaderInterface::Contactless);
+        assert_eq!(reader_interface(cstr(b"Identiv uTrust 3700 F CL Reader 00 00\0")), ReaderInterface::Contactless);
+        assert_eq!(reader_interface(cstr(b"OMNIKEY CardMan 5x21 (USB iClass Reader) 00 00\0")), ReaderInterface::Unknown);
+        assert_eq!(reader_interface(cstr(b"ACS ACR1252 Dual Reader [ACR1252 Dual Reader ICC] 00 01\0")), ReaderInterface::Contact);
+        assert_eq!(reader_interface(cstr(b"ACS ACR1252 Dual Reader [ACR1252 Dual Reader SAM] 00 01\0")), ReaderInterface::Contact);
+        // Markers must be whole words.
+        assert_eq!(reader_interface(cstr(b"Gemalto PC Twin Reader (CLASSIC) 00 00\0")), ReaderInterface::Unknown);
+    }
+
+    #[test]
+    fn atr_matches_mask() {
+        let pattern = b"\x3B\x8F\x80\x01";
+        let mask = b"\xFF\xFF\x00\xFF";
+        // Differs only on masked-out bits.
+        assert!(atr_matches(b"\x3B\x8F\x12\x01", pattern, mask));
+        // Differs on a masked-in bit.
+        assert!(!atr_matches(b"\x3B\x8F\x80\x02", pattern, mask));
+        // Lengths differ.
+        assert!(!atr_matches(b"\x3B\x8F\x80", pattern, mask));
+        assert!(!atr_matches(b"\x3B\x8F\x80\x01\x00", pattern, mask));
+    }
+
+    #[test]
+    fn atr_registry_first_match() {
+        let mut registry = AtrRegistry::new();
+        registry.add(MIFARE_ATR, &[0xFF; 20], "MIFARE Classic 1K");
+        let mut mask = [0xFF; 20];
+        mask[13] = 0x00;
+        mask[14] = 0x00;
+        registry.add(MIFARE_ATR, &mask, "MIFARE");
+        registry.add(MIFARE_ATR, &[0x00; 20], "Any");
+
+        assert_eq!(registry.match_card(MIFARE_ATR), Some("MIFARE Classic 1K"));
+        let mut atr = MIFARE_ATR.to_vec();
+        atr[14] = 0x02;
+        assert_eq!(registry.match_card(&atr), Some("MIFARE"));
+        assert_eq!(registry.match_card(&[0x3B; 20]), Some("Any"));
+        assert_eq!(registry.match_card(b"\x3B\x00"), None);
+    }
+
+    #[test]
+    fn inverse_convention() {
+        // The initial character of the inverse convention, 3F, is received
+        // as 03 by readers which do not convert it.
+        assert_eq!(convert_inverse_convention(0x3F), 0x03);
+        assert_eq!(convert_inverse_convention(0x03), 0x3F);
+        for b in 0..256 {
+            let b = b as u8;
+            assert_eq!(convert_inverse_convention(convert_inverse_convention(b)), b);
+        }
+    }
+}
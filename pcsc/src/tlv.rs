@@ -0,0 +1,230 @@
+//! Parsing and building of BER-TLV encoded data.
+//!
+//! Many structures used with smart cards are BER-TLV (Tag, Length,
+//! Value) encoded, for example FCI templates returned by the SELECT
+//! command, and the feature list returned by the `GET_FEATURE_REQUEST`
+//! control code. See [ISO 7816 Part 4][1] section 5.2 for the encoding
+//! rules.
+//!
+//! Tags are represented as `u32`s holding the bytes of the tag in
+//! big-endian order, as they are usually written in the standards. For
+//! example, the two-byte tag `9F 38` is `0x9F38`.
+//!
+//! Only definite lengths of up to 4 bytes are supported. Constructed
+//! values are not parsed recursively; call `parse_tlv` again on the value
+//! to parse its contents.
+//!
+//! [1]: http://www.cardwerk.com/smartcards/smartcard_standard_ISO7816-4_annex-d.aspx
+
+/// Possible TLV parsing errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TlvError {
+    /// The data ended in the middle of a tag, length or value.
+    Truncated,
+    /// The tag is longer than 4 bytes.
+    TagTooLong,
+    /// The length is indefinite, or longer than 4 bytes.
+    UnsupportedLength,
+}
+
+impl std::error::Error for TlvError {
+    fn description(&self) -> &str {
+        match *self {
+            TlvError::Truncated => "The TLV data is truncated",
+            TlvError::TagTooLong => "The TLV tag is longer than 4 bytes",
+            TlvError::UnsupportedLength => "The TLV length is indefinite or longer than 4 bytes",
+        }
+    }
+}
+
+impl std::fmt::Display for TlvError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        f.write_str(std::error::Error::description(self))
+    }
+}
+
+/// An iterator over the TLV data objects in a buffer.
+///
+/// The iterator does not perform any copying or allocations; the values
+/// are yielded directly from the underlying buffer.
+///
+/// After an error is yielded, the iterator is exhausted.
+#[derive(Clone)]
+pub struct Tlvs<'buf> {
+    buf: &'buf [u8],
+}
+
+/// Parse the BER-TLV data objects in `bytes`.
+///
+/// Returns an iterator over `(tag, value)` pairs. Padding bytes (`00` or
+/// `FF`) between data objects are skipped.
+pub fn parse_tlv<'buf>(bytes: &'buf [u8]) -> Tlvs<'buf> {
+    Tlvs {
+        buf: bytes,
+    }
+}
+
+impl<'buf> Tlvs<'buf> {
+    fn next_byte(&mut self) -> Result<u8, TlvError> {
+        match self.buf.split_first() {
+            Some((&b, rest)) => {
+                self.buf = rest;
+                Ok(b)
+            }
+            None => Err(TlvError::Truncated),
+        }
+    }
+
+    fn parse_tag(&mut self) -> Result<u32, TlvError> {
+        let first = self.next_byte()?;
+        let mut tag = u32::from(first);
+        if first & 0x1F == 0x1F {
+            for i in 0.. {
+                if i == 3 {
+                    return Err(TlvError::TagTooLong);
+                }
+                let b = self.next_byte()?;
+                tag = (tag << 8) | u32::from(b);
+                if b & 0x80 == 0 {
+                    break;
+                }
+            }
+        }
+        Ok(tag)
+    }
+
+    fn parse_length(&mut self) -> Result<usize, TlvError> {
+        let first = self.next_byte()?;
+        if first < 0x80 {
+            return Ok(first as usize);
+        }
+        let num_bytes = first & 0x7F;
+        if num_bytes == 0 || num_bytes > 4 {
+            return Err(TlvError::UnsupportedLength);
+        }
+        let mut len = 0usize;
+        for _ in 0..num_bytes {
+            len = (len << 8) | self.next_byte()? as usize;
+        }
+        Ok(len)
+    }
+
+    fn parse_one(&mut self) -> Result<(u32, &'buf [u8]), TlvError> {
+        let tag = self.parse_tag()?;
+        let len = self.parse_length()?;
+        if self.buf.len() < len {
+            return Err(TlvError::Truncated);
+        }
+        let (value, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok((tag, value))
+    }
+}
+
+impl<'buf> Iterator for Tlvs<'buf> {
+    type Item = Result<(u32, &'buf [u8]), TlvError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((&b, rest)) = self.buf.split_first() {
+            if b != 0x00 && b != 0xFF {
+                break;
+            }
+            self.buf = rest;
+        }
+        if self.buf.is_empty() {
+            return None;
+        }
+
+        let result = self.parse_one();
+        if result.is_err() {
+            self.buf = &[];
+        }
+        Some(result)
+    }
+}
+
+/// Append a BER-TLV data object to `out`.
+///
+/// `tag` is given in the same form as returned by `parse_tlv`. The length
+/// is encoded in the shortest possible form.
+pub fn write_tlv(tag: u32, value: &[u8], out: &mut Vec<u8>) {
+    let tag_bytes = [(tag >> 24) as u8, (tag >> 16) as u8, (tag >> 8) as u8, tag as u8];
+    let tag_start = tag_bytes.iter().position(|&b| b != 0).unwrap_or(3);
+    out.extend_from_slice(&tag_bytes[tag_start..]);
+
+    let len = value.len();
+    assert!(len <= std::u32::MAX as usize);
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let len_bytes = [(len >> 24) as u8, (len >> 16) as u8, (len >> 8) as u8, len as u8];
+        let len_start = len_bytes.iter().position(|&b| b != 0).unwrap();
+        out.push(0x80 | (4 - len_start) as u8);
+        out.extend_from_slice(&len_bytes[len_start..]);
+    }
+
+    out.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(bytes: &[u8]) -> Vec<Result<(u32, &[u8]), TlvError>> {
+        parse_tlv(bytes).collect()
+    }
+
+    #[test]
+    fn single_byte_tags() {
+        assert_eq!(parse(b"\x84\x02\xA0\x00\x50\x00"), [Ok((0x84, &b"\xA0\x00"[..])), Ok((0x50, &b""[..]))]);
+        assert!(parse(b"").is_empty());
+    }
+
+    #[test]
+    fn multi_byte_tags() {
+        assert_eq!(parse(b"\x9F\x38\x01\xAA"), [Ok((0x9F38, &b"\xAA"[..]))]);
+        assert_eq!(parse(b"\x5F\x81\x01\x00"), [Ok((0x5F8101, &b""[..]))]);
+        assert_eq!(parse(b"\x5F\x81\x81\x81\x01\x00"), [Err(TlvError::TagTooLong)]);
+    }
+
+    #[test]
+    fn long_form_lengths() {
+        let mut data = vec![0x53, 0x81, 0x80];
+        data.extend_from_slice(&[0xAB; 0x80]);
+        assert_eq!(parse(&data), [Ok((0x53, &[0xAB; 0x80][..]))]);
+
+        let mut data = vec![0x53, 0x82, 0x01, 0x00];
+        data.extend_from_slice(&[0xCD; 0x100]);
+        assert_eq!(parse(&data), [Ok((0x53, &[0xCD; 0x100][..]))]);
+
+        // Indefinite and overlong lengths.
+        assert_eq!(parse(b"\x53\x80\x00\x00"), [Err(TlvError::UnsupportedLength)]);
+        assert_eq!(parse(b"\x53\x85\x00\x00\x00\x00\x01\x00"), [Err(TlvError::UnsupportedLength)]);
+    }
+
+    #[test]
+    fn truncated() {
+        // In the tag, the length and the value.
+        assert_eq!(parse(b"\x9F"), [Err(TlvError::Truncated)]);
+        assert_eq!(parse(b"\x84"), [Err(TlvError::Truncated)]);
+        assert_eq!(parse(b"\x84\x82\x01"), [Err(TlvError::Truncated)]);
+        assert_eq!(parse(b"\x84\x03\xA0\x00"), [Err(TlvError::Truncated)]);
+        // The iterator is exhausted after an error.
+        assert_eq!(parse(b"\x50\x00\x84\x03\xA0"), [Ok((0x50, &b""[..])), Err(TlvError::Truncated)]);
+    }
+
+    #[test]
+    fn padding() {
+        assert_eq!(parse(b"\x00\xFF\x50\x00\x00"), [Ok((0x50, &b""[..]))]);
+    }
+
+    #[test]
+    fn write_round_trip() {
+        let long_value = [0x11; 0x1234];
+        let mut out = Vec::new();
+        write_tlv(0x84, b"\xA0\x00", &mut out);
+        write_tlv(0x9F38, &long_value, &mut out);
+        assert_eq!(&out[..7], b"\x84\x02\xA0\x00\x9F\x38\x82");
+        assert_eq!(parse(&out), [Ok((0x84, &b"\xA0\x00"[..])), Ok((0x9F38, &long_value[..]))]);
+    }
+}